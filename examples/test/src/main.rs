@@ -13,7 +13,7 @@ use comm::select::{Select};
 
 fn main() {
     let (core_ctrl, core_events) = ToxControl::new(ToxOptions::new()).unwrap();
-    let (av_ctrl, av_events) = core_ctrl.av(2).unwrap();
+    let (av_ctrl, av_events) = core_ctrl.av(2).unwrap().unwrap();
 
     let mut audiomap = HashMap::new();
 
@@ -69,17 +69,22 @@ fn main() {
                     };
                 },
                 GroupMessage(_, _, msg) => println!("GroupMessage(_, _, {:?})", msg),
+                GroupAction(_, _, act)  => println!("GroupAction(_, _, {:?})", act),
                 GroupNamelistChange(gnum, pnum, change) => {
                     println!("GroupNamelistChange(..) ");
                     if change == ChatChange::PeerDel {
                         audiomap.remove(&(gnum, pnum));
                     }
                 },
+                GroupTitle(..)          => println!("GroupTitle(..)          "),
                 FileSendRequest(..)     => println!("FileSendRequest(..)     "),
                 FileControl(..)         => println!("FileControl(..)         "),
                 FileData(..)            => println!("FileData(..)            "),
                 AvatarInfo(..)          => println!("AvatarInfo(..)          "),
                 AvatarData(..)          => println!("AvatarData(..)          "),
+                LossyPacket(..)         => println!("LossyPacket(..)         "),
+                LosslessPacket(..)      => println!("LosslessPacket(..)      "),
+                SelfConnectionStatus(..) => println!("SelfConnectionStatus(..)"),
             }
         }
 
@@ -1,6 +1,6 @@
 use core::ll::{Tox};
 use av::ll::*;
-use av::{Event, CallSettings, CallState, Capability, AudioBit, ControlProducer, AvEvents};
+use av::{Event, Error, CallSettings, CallState, Capability, AudioBit, ControlProducer, AvEvents};
 
 use comm::{self, spsc};
 
@@ -8,29 +8,30 @@ use libc::{c_void, c_int, c_uint, c_char};
 use std::mem::{transmute, zeroed};
 use std::{self, slice};
 use std::old_io::{timer};
+use std::thread::{JoinHandle};
 
 type OneSpaceProducer<T> = spsc::one_space::Producer<'static, T>;
 
 pub enum Control {
-    Call(i32, Option<Box<CallSettings>>, i32, OneSpaceProducer<Result<i32, i32>>), 
-    Hangup(i32, OneSpaceProducer<Result<(), i32>>),
-    Answer(i32, Option<Box<CallSettings>>, OneSpaceProducer<Result<(), i32>>),
-    Reject(i32, OneSpaceProducer<Result<(), i32>>),
-    Cancel(i32, i32, OneSpaceProducer<Result<(), i32>>),
-    ChangeSettings(i32, Option<Box<CallSettings>>, OneSpaceProducer<Result<(), i32>>),
-    StopCall(i32, OneSpaceProducer<Result<(), i32>>),
-    PrepareTransmission(i32, bool, OneSpaceProducer<Result<(), i32>>),
-    KillTransmission(i32, OneSpaceProducer<Result<(), i32>>),
-    PrepareAudioFrame(i32, Vec<u8>, Vec<i16>, OneSpaceProducer<Result<(Vec<u8>, Vec<i16>), (i32, Vec<u8>, Vec<i16>)>>),
-    SendAudio(i32, Vec<u8>, OneSpaceProducer<Result<Vec<u8>, (i32, Vec<u8>)>>),
-    GetPeerCallSettings(i32, i32, OneSpaceProducer<Result<Box<CallSettings>, i32>>),
-    GetPeerId(i32, i32, OneSpaceProducer<Result<i32, i32>>),
+    Call(i32, Option<Box<CallSettings>>, i32, OneSpaceProducer<Result<i32, Error>>), 
+    Hangup(i32, OneSpaceProducer<Result<(), Error>>),
+    Answer(i32, Option<Box<CallSettings>>, OneSpaceProducer<Result<(), Error>>),
+    Reject(i32, OneSpaceProducer<Result<(), Error>>),
+    Cancel(i32, i32, OneSpaceProducer<Result<(), Error>>),
+    ChangeSettings(i32, Option<Box<CallSettings>>, OneSpaceProducer<Result<(), Error>>),
+    StopCall(i32, OneSpaceProducer<Result<(), Error>>),
+    PrepareTransmission(i32, bool, OneSpaceProducer<Result<(), Error>>),
+    KillTransmission(i32, OneSpaceProducer<Result<(), Error>>),
+    PrepareAudioFrame(i32, Vec<u8>, Vec<i16>, OneSpaceProducer<Result<(Vec<u8>, Vec<i16>), (Error, Vec<u8>, Vec<i16>)>>),
+    SendAudio(i32, Vec<u8>, OneSpaceProducer<Result<Vec<u8>, (Error, Vec<u8>)>>),
+    GetPeerCallSettings(i32, i32, OneSpaceProducer<Result<Box<CallSettings>, Error>>),
+    GetPeerId(i32, i32, OneSpaceProducer<Result<i32, Error>>),
     GetCallState(i32, OneSpaceProducer<CallState>),
-    CapabilitySupported(i32, Capability, OneSpaceProducer<Result<bool, i32>>),
-    GetActiveCount(OneSpaceProducer<Result<usize, i32>>),
-    AddAvGroupchat(OneSpaceProducer<Result<i32, i32>>),
-    JoinAvGroupchat(i32, Vec<u8>, OneSpaceProducer<Result<i32, i32>>),
-    GroupSendAudio(i32, AudioBit, OneSpaceProducer<Result<AudioBit, (i32, AudioBit)>>),
+    CapabilitySupported(i32, Capability, OneSpaceProducer<Result<bool, Error>>),
+    GetActiveCount(OneSpaceProducer<Result<usize, Error>>),
+    AddAvGroupchat(OneSpaceProducer<Result<i32, Error>>),
+    JoinAvGroupchat(i32, Vec<u8>, OneSpaceProducer<Result<i32, Error>>),
+    GroupSendAudio(i32, AudioBit, OneSpaceProducer<Result<AudioBit, (Error, AudioBit)>>),
 }
 
 pub struct Backend {
@@ -39,13 +40,14 @@ pub struct Backend {
     internal: Box<Internal>,
     control: spsc::one_space::Consumer<'static, Control>,
     _send_end: spsc::one_space::Producer<'static, ()>,
+    kill: spsc::one_space::Consumer<'static, ()>,
 }
 
 unsafe impl Send for Backend { }
 
 impl Backend {
     pub fn call(&mut self, friend_id: i32, settings: Option<Box<CallSettings>>,
-                timeout: i32) -> Result<i32, i32> {
+                timeout: i32) -> Result<i32, Error> {
         let settings = match settings {
             Some(ref s) => &**s,
             _ => &av_DefaultSettings,
@@ -57,20 +59,20 @@ impl Backend {
         };
         match res {
             0 => Ok(index),
-            _ => Err(res),
+            _ => Err(Error::from_code(res)),
         }
     }
 
-    pub fn hangup(&mut self, call_id: i32) -> Result<(), i32> {
+    pub fn hangup(&mut self, call_id: i32) -> Result<(), Error> {
         let res = unsafe { toxav_hangup(self.raw, call_id) };
         match res {
             0 => Ok(()),
-            _ => Err(res),
+            _ => Err(Error::from_code(res)),
         }
     }
 
     pub fn answer(&mut self, call_id: i32,
-                  settings: Option<Box<CallSettings>>) -> Result<(), i32> {
+                  settings: Option<Box<CallSettings>>) -> Result<(), Error> {
         let settings = match settings {
             Some(ref s) => &**s,
             _ => &av_DefaultSettings,
@@ -78,28 +80,28 @@ impl Backend {
         let res = unsafe { toxav_answer(self.raw, call_id, settings) };
         match res {
             0 => Ok(()),
-            _ => Err(res),
+            _ => Err(Error::from_code(res)),
         }
     }
 
-    pub fn reject(&mut self, call_id: i32) -> Result<(), i32> {
+    pub fn reject(&mut self, call_id: i32) -> Result<(), Error> {
         let res = unsafe { toxav_reject(self.raw, call_id, 0 as *const c_char) };
         match res {
             0 => Ok(()),
-            _ => Err(res),
+            _ => Err(Error::from_code(res)),
         }
     }
 
-    pub fn cancel(&mut self, call_id: i32, peer_id: i32) -> Result<(), i32> {
+    pub fn cancel(&mut self, call_id: i32, peer_id: i32) -> Result<(), Error> {
         let res = unsafe { toxav_cancel(self.raw, call_id, peer_id as c_int, 0 as *const c_char) };
         match res {
             0 => Ok(()),
-            _ => Err(res),
+            _ => Err(Error::from_code(res)),
         }
     }
 
     pub fn change_settings(&mut self, call_id: i32,
-                           settings: Option<Box<CallSettings>>) -> Result<(), i32>{
+                           settings: Option<Box<CallSettings>>) -> Result<(), Error>{
         let settings = match settings {
             Some(ref s) => &**s,
             _ => &av_DefaultSettings,
@@ -107,39 +109,39 @@ impl Backend {
         let res = unsafe { toxav_change_settings(self.raw, call_id, settings) };
         match res {
             0 => Ok(()),
-            _ => Err(res),
+            _ => Err(Error::from_code(res)),
         }
     }
 
-    pub fn stop_call(&mut self, call_id: i32) -> Result<(), i32> {
+    pub fn stop_call(&mut self, call_id: i32) -> Result<(), Error> {
         let res = unsafe { toxav_stop_call(self.raw, call_id) };
         match res {
             0 => Ok(()),
-            _ => Err(res),
+            _ => Err(Error::from_code(res)),
         }
     }
 
     pub fn prepare_transmission(&mut self, call_id: i32,
-                                support_video: bool) -> Result<(), i32>{
+                                support_video: bool) -> Result<(), Error>{
         let res = unsafe {
             toxav_prepare_transmission(self.raw, call_id, support_video as c_int)
         };
         match res {
             0 => Ok(()),
-            _ => Err(res),
+            _ => Err(Error::from_code(res)),
         }
     }
 
-    pub fn kill_transmission(&mut self, call_id: i32) -> Result<(), i32> {
+    pub fn kill_transmission(&mut self, call_id: i32) -> Result<(), Error> {
         let res = unsafe { toxav_kill_transmission(self.raw, call_id) };
         match res {
             0 => Ok(()),
-            _ => Err(res),
+            _ => Err(Error::from_code(res)),
         }
     }
 
     pub fn prepare_audio_frame(&mut self, _call_id: i32, _dest: Vec<u8>,
-                               _src: Vec<i16>) -> Result<(Vec<u8>, Vec<i16>), (i32, Vec<u8>, Vec<i16>)> {
+                               _src: Vec<i16>) -> Result<(Vec<u8>, Vec<i16>), (Error, Vec<u8>, Vec<i16>)> {
         // Seriously wtf
         // This piece of shit code has no comments
         // How am I supposed to wrap this shit?
@@ -147,32 +149,32 @@ impl Backend {
     }
 
     pub fn send_audio(&mut self, call_id: i32,
-                      src: Vec<u8>) -> Result<Vec<u8>, (i32, Vec<u8>)> {
+                      src: Vec<u8>) -> Result<Vec<u8>, (Error, Vec<u8>)> {
         let res = unsafe {
             toxav_send_audio(self.raw, call_id, src.as_ptr(), src.len() as c_uint)
         };
         match res {
             0 => Ok(src),
-            _ => Err((res, src)),
+            _ => Err((Error::from_code(res), src)),
         }
     }
 
     pub fn get_peer_call_settings(&mut self, call_id: i32,
-                                  peer_id: i32) -> Result<Box<CallSettings>, i32> {
+                                  peer_id: i32) -> Result<Box<CallSettings>, Error> {
         let mut settings = unsafe { zeroed() };
         let res = unsafe {
             toxav_get_peer_csettings(self.raw, call_id, peer_id as c_int, &mut settings)
         };
         match res {
             0 => Ok(Box::new(settings)),
-            _ => Err(res),
+            _ => Err(Error::from_code(res)),
         }
     }
 
-    pub fn get_peer_id(&mut self, call_id: i32, peer_id: i32) -> Result<i32, i32> {
+    pub fn get_peer_id(&mut self, call_id: i32, peer_id: i32) -> Result<i32, Error> {
         let res = unsafe { toxav_get_peer_id(self.raw, call_id, peer_id as c_int) };
         if res < 0 {
-            Err(res)
+            Err(Error::from_code(res))
         } else {
             Ok(res)
         }
@@ -184,53 +186,53 @@ impl Backend {
     }
 
     pub fn capability_supported(&mut self, call_id: i32,
-                                capability: Capability) -> Result<bool, i32> {
+                                capability: Capability) -> Result<bool, Error> {
         let res = unsafe { toxav_capability_supported(self.raw, call_id, capability) };
         match res {
             1 => Ok(true),
             0 => Ok(false),
-            _ => Err(res),
+            _ => Err(Error::from_code(res)),
         }
     }
 
-    pub fn get_active_count(&mut self) -> Result<usize, i32> {
+    pub fn get_active_count(&mut self) -> Result<usize, Error> {
         let res = unsafe { toxav_get_active_count(self.raw) };
         if res >= 0 {
             Ok(res as usize)
         } else {
-            Err(res)
+            Err(Error::from_code(res))
         }
     }
 
-    pub fn add_av_groupchat(&mut self) -> Result<i32, i32> {
+    pub fn add_av_groupchat(&mut self) -> Result<i32, Error> {
         let ip = &mut *self.internal as *mut _ as *mut c_void;
         let ret = unsafe {
             toxav_add_av_groupchat(self.raw_tox, Some(on_group_audio),
                                    ip)
         };
         match ret {
-            -1 => Err(ret),
+            -1 => Err(Error::ErrorUnknown),
             _ => Ok(ret),
         }
     }
 
     pub fn join_av_groupchat(&mut self, friend_id: i32,
-                             data: Vec<u8>) -> Result<i32, i32> {
+                             data: Vec<u8>) -> Result<i32, Error> {
         let ip = &mut *self.internal as *mut _ as *mut c_void;
         let ret = unsafe {
             toxav_join_av_groupchat(self.raw_tox, friend_id, data.as_ptr(),
                                     data.len() as u16, Some(on_group_audio), ip)
         };
         match ret {
-            -1 => Err(ret),
+            -1 => Err(Error::ErrorUnknown),
             _ => Ok(ret),
         }
     }
 
     pub fn group_send_audio(&mut self, group_id: i32,
-                            bit: AudioBit) -> Result<AudioBit, (i32, AudioBit)> {
+                            bit: AudioBit) -> Result<AudioBit, (Error, AudioBit)> {
         if !bit.validate() {
-            return Err((-1, bit));
+            return Err((Error::ErrorUnknown, bit));
         }
         let ret = unsafe {
             toxav_group_send_audio(self.raw_tox, group_id as c_int, bit.pcm.as_ptr(),
@@ -239,12 +241,14 @@ impl Backend {
         };
         match ret {
             0 => Ok(bit),
-            _ => Err((ret, bit)),
+            _ => Err((Error::from_code(ret), bit)),
         }
     }
 
-    pub fn new(tox: *mut Tox, max_calls: i32, send_end: spsc::one_space::Producer<'static, ()>)
-                        -> Option<(ControlProducer, AvEvents)> {
+    pub fn new(tox: *mut Tox, max_calls: i32,
+               send_end: spsc::one_space::Producer<'static, ()>,
+               kill: spsc::one_space::Consumer<'static, ()>)
+                        -> Option<(ControlProducer, AvEvents, JoinHandle)> {
         let av = unsafe { toxav_new(tox, max_calls) };
         if av.is_null() {
             return None;
@@ -279,9 +283,10 @@ impl Backend {
             internal: internal,
             control: control_recv,
             _send_end: send_end,
+            kill: kill,
         };
-        std::thread::spawn(move || backend.run());
-        Some((control_send, event_recv))
+        let thread = std::thread::spawn(move || backend.run());
+        Some((control_send, event_recv, thread))
     }
 
     fn run(mut self) {
@@ -291,6 +296,11 @@ impl Backend {
                 break 'outer;
             }
 
+            // The tox backend is shutting down
+            if self.kill.recv_async() == Err(comm::Error::Disconnected) {
+                break 'outer;
+            }
+
             'inner: loop {
                 match self.control.recv_async() {
                     Ok(ctrl) => self.control(ctrl),
@@ -306,44 +316,63 @@ impl Backend {
 
     fn control(&mut self, ctrl: Control) {
         match ctrl {
-            Control::Call(friend_id, settings, timeout, ret) =>
-                ret.send(self.call(friend_id, settings, timeout)).unwrap(),
-            Control::Hangup(call_id, ret) =>
-                ret.send(self.hangup(call_id)).unwrap(),
-            Control::Answer(call_id, settings, ret) =>
-                ret.send(self.answer(call_id, settings)).unwrap(),
-            Control::Reject(call_id, ret) =>
-                ret.send(self.reject(call_id)).unwrap(),
-            Control::Cancel(call_id, peer_id, ret) =>
-                ret.send(self.cancel(call_id, peer_id)).unwrap(),
-            Control::ChangeSettings(call_id, settings, ret) =>
-                ret.send(self.change_settings(call_id, settings)).unwrap(),
-            Control::StopCall(call_id, ret) =>
-                ret.send(self.stop_call(call_id)).unwrap(),
-            Control::PrepareTransmission(call_id, support_video, ret) =>
-                ret.send(self.prepare_transmission(call_id, support_video)).unwrap(),
-            Control::KillTransmission(call_id, ret) =>
-                ret.send(self.kill_transmission(call_id)).unwrap(),
-            Control::PrepareAudioFrame(call_id, dest, frame, ret) =>
-                ret.send(self.prepare_audio_frame(call_id, dest, frame)).unwrap(),
-            Control::SendAudio(call_id, frame, ret) =>
-                ret.send(self.send_audio(call_id, frame)).unwrap(),
-            Control::GetPeerCallSettings(call_id, peer_id, ret) =>
-                ret.send(self.get_peer_call_settings(call_id, peer_id)).unwrap(),
-            Control::GetPeerId(call_id, peer_id, ret) =>
-                ret.send(self.get_peer_id(call_id, peer_id)).unwrap(),
-            Control::GetCallState(call_id, ret) =>
-                ret.send(self.get_call_state(call_id)).unwrap(),
-            Control::CapabilitySupported(call_id, capability, ret) =>
-                ret.send(self.capability_supported(call_id, capability)).unwrap(),
-            Control::GetActiveCount(ret) =>
-                ret.send(self.get_active_count()).unwrap(),
-            Control::AddAvGroupchat(ret) =>
-                ret.send(self.add_av_groupchat()).unwrap(),
-            Control::JoinAvGroupchat(friend_id, data, ret) =>
-                ret.send(self.join_av_groupchat(friend_id, data)).unwrap(),
-            Control::GroupSendAudio(group_id, bit, ret) =>
-                ret.send(self.group_send_audio(group_id, bit)).unwrap(),
+            Control::Call(friend_id, settings, timeout, ret) => {
+                let _ = ret.send(self.call(friend_id, settings, timeout));
+            },
+            Control::Hangup(call_id, ret) => {
+                let _ = ret.send(self.hangup(call_id));
+            },
+            Control::Answer(call_id, settings, ret) => {
+                let _ = ret.send(self.answer(call_id, settings));
+            },
+            Control::Reject(call_id, ret) => {
+                let _ = ret.send(self.reject(call_id));
+            },
+            Control::Cancel(call_id, peer_id, ret) => {
+                let _ = ret.send(self.cancel(call_id, peer_id));
+            },
+            Control::ChangeSettings(call_id, settings, ret) => {
+                let _ = ret.send(self.change_settings(call_id, settings));
+            },
+            Control::StopCall(call_id, ret) => {
+                let _ = ret.send(self.stop_call(call_id));
+            },
+            Control::PrepareTransmission(call_id, support_video, ret) => {
+                let _ = ret.send(self.prepare_transmission(call_id, support_video));
+            },
+            Control::KillTransmission(call_id, ret) => {
+                let _ = ret.send(self.kill_transmission(call_id));
+            },
+            Control::PrepareAudioFrame(call_id, dest, frame, ret) => {
+                let _ = ret.send(self.prepare_audio_frame(call_id, dest, frame));
+            },
+            Control::SendAudio(call_id, frame, ret) => {
+                let _ = ret.send(self.send_audio(call_id, frame));
+            },
+            Control::GetPeerCallSettings(call_id, peer_id, ret) => {
+                let _ = ret.send(self.get_peer_call_settings(call_id, peer_id));
+            },
+            Control::GetPeerId(call_id, peer_id, ret) => {
+                let _ = ret.send(self.get_peer_id(call_id, peer_id));
+            },
+            Control::GetCallState(call_id, ret) => {
+                let _ = ret.send(self.get_call_state(call_id));
+            },
+            Control::CapabilitySupported(call_id, capability, ret) => {
+                let _ = ret.send(self.capability_supported(call_id, capability));
+            },
+            Control::GetActiveCount(ret) => {
+                let _ = ret.send(self.get_active_count());
+            },
+            Control::AddAvGroupchat(ret) => {
+                let _ = ret.send(self.add_av_groupchat());
+            },
+            Control::JoinAvGroupchat(friend_id, data, ret) => {
+                let _ = ret.send(self.join_av_groupchat(friend_id, data));
+            },
+            Control::GroupSendAudio(group_id, bit, ret) => {
+                let _ = ret.send(self.group_send_audio(group_id, bit));
+            },
         }
     }
 }
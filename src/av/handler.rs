@@ -0,0 +1,46 @@
+//! Callback style access to the AV events.
+
+use av::{Event, AvEvents, AudioBit};
+use av::Event::*;
+
+/// Callbacks for the events of an AV session. Every method corresponds to the variant
+/// of `Event` with the same name and does nothing by default.
+#[allow(unused_variables)]
+pub trait AvHandler {
+    fn on_invite(&mut self, call: i32) { }
+    fn on_ringing(&mut self, call: i32) { }
+    fn on_start(&mut self, call: i32) { }
+    fn on_cancel(&mut self, call: i32) { }
+    fn on_reject(&mut self, call: i32) { }
+    fn on_end(&mut self, call: i32) { }
+    fn on_request_timeout(&mut self, call: i32) { }
+    fn on_peer_timeout(&mut self, call: i32) { }
+    fn on_peer_cs_change(&mut self, call: i32) { }
+    fn on_self_cs_change(&mut self, call: i32) { }
+    fn on_group_audio(&mut self, gnum: i32, pnum: i32, audio: AudioBit) { }
+
+    /// Call the method that corresponds to the event
+    fn handle(&mut self, event: Event) {
+        match event {
+            Invite(call)                => self.on_invite(call),
+            Ringing(call)               => self.on_ringing(call),
+            Start(call)                 => self.on_start(call),
+            Cancel(call)                => self.on_cancel(call),
+            Reject(call)                => self.on_reject(call),
+            End(call)                   => self.on_end(call),
+            RequestTimeout(call)        => self.on_request_timeout(call),
+            PeerTimeout(call)           => self.on_peer_timeout(call),
+            PeerCsChange(call)          => self.on_peer_cs_change(call),
+            SelfCsChange(call)          => self.on_self_cs_change(call),
+            GroupAudio(gnum, pnum, bit) => self.on_group_audio(gnum, pnum, bit),
+        }
+    }
+
+    /// Pass all events received on `events` to `handle`. Returns when the AV backend
+    /// thread has stopped
+    fn run(&mut self, events: &AvEvents) {
+        while let Ok(event) = events.recv_sync() {
+            self.handle(event);
+        }
+    }
+}
@@ -1,9 +1,16 @@
+use std::{error, fmt};
+use std::thread::{JoinHandle};
+
 use core::ll::{Tox};
+use core::{Disconnected};
 
 use comm::{spsc};
 
+pub use self::handler::{AvHandler};
+
 pub mod ll;
 mod backend;
+mod handler;
 
 #[derive(Clone, Debug)]
 pub enum Event {
@@ -40,26 +47,123 @@ pub enum CallState {
     HungUp,
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
+/// Errors returned by toxav
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Error {
-    ErrorNone                   = 0,
-    ErrorUnknown                = -1,
-    ErrorNoCall                 = -20,
-    ErrorInvalidState           = -21,
-    ErrorAlreadyInCallWithPeer  = -22,
-    ErrorReachedCallLimit       = -23,
-    ErrorInitializingCodecs     = -30,
-    ErrorSettingVideoResolution = -31,
-    ErrorSettingVideoBitrate    = -32,
-    ErrorSplittingVideoPayload  = -33,
-    ErrorEncodingVideo          = -34,
-    ErrorEncodingAudio          = -35,
-    ErrorSendingPayload         = -40,
-    ErrorCreatingRtpSessions    = -41,
-    ErrorNoRtpSession           = -50,
-    ErrorInvalidCodecState      = -51,
-    ErrorPacketTooLarge         = -52,
+    ErrorNone,
+    ErrorUnknown,
+    ErrorNoCall,
+    ErrorInvalidState,
+    ErrorAlreadyInCallWithPeer,
+    ErrorReachedCallLimit,
+    ErrorInitializingCodecs,
+    ErrorSettingVideoResolution,
+    ErrorSettingVideoBitrate,
+    ErrorSplittingVideoPayload,
+    ErrorEncodingVideo,
+    ErrorEncodingAudio,
+    ErrorSendingPayload,
+    ErrorCreatingRtpSessions,
+    ErrorNoRtpSession,
+    ErrorInvalidCodecState,
+    ErrorPacketTooLarge,
+    /// An error code not known to this library
+    Unknown(i32),
+    /// The backend thread has stopped
+    Disconnected,
+}
+
+impl Error {
+    /// Convert a toxav error code into an `Error`
+    pub fn from_code(code: i32) -> Error {
+        match code {
+            0   => Error::ErrorNone,
+            -1  => Error::ErrorUnknown,
+            -20 => Error::ErrorNoCall,
+            -21 => Error::ErrorInvalidState,
+            -22 => Error::ErrorAlreadyInCallWithPeer,
+            -23 => Error::ErrorReachedCallLimit,
+            -30 => Error::ErrorInitializingCodecs,
+            -31 => Error::ErrorSettingVideoResolution,
+            -32 => Error::ErrorSettingVideoBitrate,
+            -33 => Error::ErrorSplittingVideoPayload,
+            -34 => Error::ErrorEncodingVideo,
+            -35 => Error::ErrorEncodingAudio,
+            -40 => Error::ErrorSendingPayload,
+            -41 => Error::ErrorCreatingRtpSessions,
+            -50 => Error::ErrorNoRtpSession,
+            -51 => Error::ErrorInvalidCodecState,
+            -52 => Error::ErrorPacketTooLarge,
+            n   => Error::Unknown(n),
+        }
+    }
+
+    /// Returns the toxav error code of this error. `Disconnected` has no toxav
+    /// equivalent and is reported as `ErrorUnknown`
+    pub fn code(&self) -> i32 {
+        match *self {
+            Error::ErrorNone                   => 0,
+            Error::ErrorUnknown                => -1,
+            Error::ErrorNoCall                 => -20,
+            Error::ErrorInvalidState           => -21,
+            Error::ErrorAlreadyInCallWithPeer  => -22,
+            Error::ErrorReachedCallLimit       => -23,
+            Error::ErrorInitializingCodecs     => -30,
+            Error::ErrorSettingVideoResolution => -31,
+            Error::ErrorSettingVideoBitrate    => -32,
+            Error::ErrorSplittingVideoPayload  => -33,
+            Error::ErrorEncodingVideo          => -34,
+            Error::ErrorEncodingAudio          => -35,
+            Error::ErrorSendingPayload         => -40,
+            Error::ErrorCreatingRtpSessions    => -41,
+            Error::ErrorNoRtpSession           => -50,
+            Error::ErrorInvalidCodecState      => -51,
+            Error::ErrorPacketTooLarge         => -52,
+            Error::Unknown(n)                  => n,
+            Error::Disconnected                => -1,
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::ErrorNone                   => "no error",
+            Error::ErrorUnknown                => "unknown error",
+            Error::ErrorNoCall                 => "no such call",
+            Error::ErrorInvalidState           => "invalid call state",
+            Error::ErrorAlreadyInCallWithPeer  => "already in a call with the peer",
+            Error::ErrorReachedCallLimit       => "reached the call limit",
+            Error::ErrorInitializingCodecs     => "failed to initialize codecs",
+            Error::ErrorSettingVideoResolution => "failed to set the video resolution",
+            Error::ErrorSettingVideoBitrate    => "failed to set the video bitrate",
+            Error::ErrorSplittingVideoPayload  => "failed to split the video payload",
+            Error::ErrorEncodingVideo          => "failed to encode video",
+            Error::ErrorEncodingAudio          => "failed to encode audio",
+            Error::ErrorSendingPayload         => "failed to send the payload",
+            Error::ErrorCreatingRtpSessions    => "failed to create RTP sessions",
+            Error::ErrorNoRtpSession           => "no RTP session",
+            Error::ErrorInvalidCodecState      => "invalid codec state",
+            Error::ErrorPacketTooLarge         => "packet too large",
+            Error::Unknown(_)                  => "unknown error code",
+            Error::Disconnected                => "backend thread has stopped",
+        }
+    }
+}
+
+impl From<Disconnected> for Error {
+    fn from(_: Disconnected) -> Error {
+        Error::Disconnected
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Unknown(n) => write!(fmt, "unknown error code {}", n),
+            _ => fmt.write_str(error::Error::description(self)),
+        }
+    }
 }
 
 #[repr(C)]
@@ -102,144 +206,198 @@ impl AudioBit {
 
 pub struct AvControl {
     control: spsc::one_space::Producer<'static, backend::Control>,
+    thread: JoinHandle,
 }
 
 type ControlProducer = spsc::one_space::Producer<'static, backend::Control>;
 pub type AvEvents = spsc::bounded::Consumer<'static, Event>;
 
+// See the `forward!` macro in `core`.
 macro_rules! forward {
     ($slf:expr, $name:expr, ($($pp:ident),+), ->) => {{
-            let (snd, rcv) = spsc::one_space::new();
-            $slf.control.send($name($($pp),*, snd)).map_err(|e|e.1).unwrap();
-            rcv.recv_sync().unwrap()
+        let (snd, rcv) = spsc::one_space::new();
+        match $slf.control.send($name($($pp),*, snd)) {
+            Ok(()) => match rcv.recv_sync() {
+                Ok(res) => res,
+                Err(_) => Err(Error::Disconnected),
+            },
+            Err(_) => Err(Error::Disconnected),
+        }
     }};
     ($slf:expr, $name:expr, ->) => {{
-            let (snd, rcv) = spsc::one_space::new();
-            $slf.control.send($name(snd)).map_err(|e|e.1).unwrap();
-            rcv.recv_sync().unwrap()
+        let (snd, rcv) = spsc::one_space::new();
+        match $slf.control.send($name(snd)) {
+            Ok(()) => match rcv.recv_sync() {
+                Ok(res) => res,
+                Err(_) => Err(Error::Disconnected),
+            },
+            Err(_) => Err(Error::Disconnected),
+        }
     }};
-    ($slf:expr, $name:expr, ($($pp:ident),+)) => {{
-            $slf.control.send($name($($pp),*)).unwrap()
+    ($slf:expr, $name:expr, ($($pp:ident),+), =>) => {{
+        let (snd, rcv) = spsc::one_space::new();
+        match $slf.control.send($name($($pp),*, snd)) {
+            Ok(()) => rcv.recv_sync().map_err(|_| Error::Disconnected),
+            Err(_) => Err(Error::Disconnected),
+        }
     }};
-    ($slf:expr, $name:expr) => {
-            $slf.control.send($name).unwrap()
-    };
-
 }
 
 impl AvControl {
     #[inline]
     pub fn new(tox: *mut Tox, max_calls: i32,
-               send_end: spsc::one_space::Producer<'static, ()>)
+               send_end: spsc::one_space::Producer<'static, ()>,
+               kill: spsc::one_space::Consumer<'static, ()>)
                                 -> Option<(AvControl, AvEvents)> {
-        match backend::Backend::new(tox, max_calls, send_end) {
-            Some((ctrl, events)) => Some((AvControl { control: ctrl }, events)),
+        match backend::Backend::new(tox, max_calls, send_end, kill) {
+            Some((ctrl, events, thread)) => {
+                Some((AvControl { control: ctrl, thread: thread }, events))
+            },
             None => return None,
         }
     }
 
+    /// Stop the AV session and wait until it has been torn down
+    #[inline]
+    pub fn shutdown(self) {
+        let AvControl { control, thread } = self;
+        drop(control);
+        let _ = thread.join();
+    }
+
     #[inline]
     pub fn call(&self, friend_id: i32, settings: Option<Box<CallSettings>>,
-                timeout: i32) -> Result<i32, i32> {
+                timeout: i32) -> Result<i32, Error> {
         forward!(self, backend::Control::Call, (friend_id, settings, timeout), ->)
     }
 
     #[inline]
-    pub fn hangup(&self, call_id: i32) -> Result<(), i32> {
+    pub fn hangup(&self, call_id: i32) -> Result<(), Error> {
         forward!(self, backend::Control::Hangup, (call_id), ->)
     }
 
     #[inline]
     pub fn answer(&self, call_id: i32,
-                  settings: Option<Box<CallSettings>>) -> Result<(), i32> {
+                  settings: Option<Box<CallSettings>>) -> Result<(), Error> {
         forward!(self, backend::Control::Answer, (call_id, settings), ->)
     }
 
     #[inline]
-    pub fn reject(&self, call_id: i32) -> Result<(), i32> {
+    pub fn reject(&self, call_id: i32) -> Result<(), Error> {
         forward!(self, backend::Control::Reject, (call_id), ->)
     }
 
     #[inline]
-    pub fn cancel(&self, call_id: i32, peer_id: i32) -> Result<(), i32> {
+    pub fn cancel(&self, call_id: i32, peer_id: i32) -> Result<(), Error> {
         forward!(self, backend::Control::Cancel, (call_id, peer_id), ->)
     }
 
     #[inline]
     pub fn change_settings(&self, call_id: i32,
-                           settings: Option<Box<CallSettings>>) -> Result<(), i32>{
+                           settings: Option<Box<CallSettings>>) -> Result<(), Error>{
         forward!(self, backend::Control::ChangeSettings, (call_id, settings), ->)
     }
 
     #[inline]
-    pub fn stop_call(&self, call_id: i32) -> Result<(), i32> {
+    pub fn stop_call(&self, call_id: i32) -> Result<(), Error> {
         forward!(self, backend::Control::StopCall, (call_id), ->)
     }
 
     #[inline]
     pub fn prepare_transmission(&self, call_id: i32,
-                                support_video: bool) -> Result<(), i32>{
+                                support_video: bool) -> Result<(), Error>{
         forward!(self, backend::Control::PrepareTransmission, (call_id, support_video), ->)
     }
 
     #[inline]
-    pub fn kill_transmission(&self, call_id: i32) -> Result<(), i32> {
+    pub fn kill_transmission(&self, call_id: i32) -> Result<(), Error> {
         forward!(self, backend::Control::KillTransmission, (call_id), ->)
     }
 
     #[inline]
     pub fn prepare_audio_frame(&self, call_id: i32, dest: Vec<u8>,
-                               src: Vec<i16>) -> Result<(Vec<u8>, Vec<i16>), (i32, Vec<u8>, Vec<i16>)> {
-        forward!(self, backend::Control::PrepareAudioFrame, (call_id, dest, src), ->)
+                               src: Vec<i16>) -> Result<(Vec<u8>, Vec<i16>), (Error, Vec<u8>, Vec<i16>)> {
+        let (snd, rcv) = spsc::one_space::new();
+        match self.control.send(backend::Control::PrepareAudioFrame(call_id, dest, src, snd)) {
+            Ok(()) => match rcv.recv_sync() {
+                Ok(res) => res,
+                Err(_) => Err((Error::Disconnected, Vec::new(), Vec::new())),
+            },
+            Err((_, backend::Control::PrepareAudioFrame(_, dest, src, _))) =>
+                Err((Error::Disconnected, dest, src)),
+            Err(_) => unreachable!(),
+        }
     }
 
     #[inline]
     pub fn send_audio(&self, call_id: i32,
-                      src: Vec<u8>) -> Result<Vec<u8>, (i32, Vec<u8>)> {
-        forward!(self, backend::Control::SendAudio, (call_id, src), ->)
+                      src: Vec<u8>) -> Result<Vec<u8>, (Error, Vec<u8>)> {
+        let (snd, rcv) = spsc::one_space::new();
+        match self.control.send(backend::Control::SendAudio(call_id, src, snd)) {
+            Ok(()) => match rcv.recv_sync() {
+                Ok(res) => res,
+                Err(_) => Err((Error::Disconnected, Vec::new())),
+            },
+            Err((_, backend::Control::SendAudio(_, src, _))) =>
+                Err((Error::Disconnected, src)),
+            Err(_) => unreachable!(),
+        }
     }
 
     #[inline]
     pub fn get_peer_call_settings(&self, call_id: i32,
-                                  peer_id: i32) -> Result<Box<CallSettings>, i32> {
+                                  peer_id: i32) -> Result<Box<CallSettings>, Error> {
         forward!(self, backend::Control::GetPeerCallSettings, (call_id, peer_id), ->)
     }
 
     #[inline]
-    pub fn get_peer_id(&self, call_id: i32, peer_id: i32) -> Result<i32, i32> {
+    pub fn get_peer_id(&self, call_id: i32, peer_id: i32) -> Result<i32, Error> {
         forward!(self, backend::Control::GetPeerId, (call_id, peer_id), ->)
     }
 
     #[inline]
-    pub fn get_call_state(&self, call_id: i32) -> CallState {
-        forward!(self, backend::Control::GetCallState, (call_id), ->)
+    pub fn get_call_state(&self, call_id: i32) -> Result<CallState, Error> {
+        forward!(self, backend::Control::GetCallState, (call_id), =>)
     }
 
     #[inline]
     pub fn capability_supported(&self, call_id: i32,
-                                capability: Capability) -> Result<bool, i32> {
+                                capability: Capability) -> Result<bool, Error> {
         forward!(self, backend::Control::CapabilitySupported, (call_id, capability), ->)
     }
 
     #[inline]
-    pub fn get_active_count(&self) -> Result<usize, i32> {
+    pub fn get_active_count(&self) -> Result<usize, Error> {
         forward!(self, backend::Control::GetActiveCount, ->)
     }
 
     #[inline]
-    pub fn add_av_groupchat(&self) -> Result<i32, i32> {
+    pub fn add_av_groupchat(&self) -> Result<i32, Error> {
         forward!(self, backend::Control::AddAvGroupchat, ->)
     }
 
     #[inline]
     pub fn join_av_groupchat(&self, friend_id: i32,
-                             data: Vec<u8>) -> Result<i32, i32> {
+                             data: Vec<u8>) -> Result<i32, Error> {
         forward!(self, backend::Control::JoinAvGroupchat, (friend_id, data), ->)
     }
 
     #[inline]
     pub fn group_send_audio(&self, group_id: i32,
-                            bit: AudioBit) -> Result<AudioBit, (i32, AudioBit)> {
-        forward!(self, backend::Control::GroupSendAudio, (group_id, bit), ->)
+                            bit: AudioBit) -> Result<AudioBit, (Error, AudioBit)> {
+        let (snd, rcv) = spsc::one_space::new();
+        match self.control.send(backend::Control::GroupSendAudio(group_id, bit, snd)) {
+            Ok(()) => match rcv.recv_sync() {
+                Ok(res) => res,
+                Err(_) => {
+                    let bit = AudioBit { pcm: Vec::new(), samples: 0, channels: 0,
+                                         sample_rate: 0 };
+                    Err((Error::Disconnected, bit))
+                },
+            },
+            Err((_, backend::Control::GroupSendAudio(_, bit, _))) =>
+                Err((Error::Disconnected, bit)),
+            Err(_) => unreachable!(),
+        }
     }
 }
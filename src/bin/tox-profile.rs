@@ -0,0 +1,308 @@
+//! Inspect and edit tox profiles without starting a tox instance.
+//!
+//! Encrypted profiles are decrypted with the passphrase in `TOX_PASSPHRASE` or
+//! the one entered on stdin. Edited profiles are written atomically and the
+//! previous version is kept as `<profile>.1`.
+
+#![feature(env, fs, io, path, collections, core)]
+
+extern crate tox;
+
+use std::{env};
+use std::fs::{File};
+use std::io::{self, Read, Write};
+use std::path::{Path};
+
+use tox::core::{ClientId, UserStatus, ToxString, MAX_NAME_LENGTH,
+                MAX_STATUSMESSAGE_LENGTH};
+use tox::encryptsave;
+use tox::profile;
+use tox::savefile::{SaveFile, Friend, FriendStatus};
+use tox::util::{random_bytes};
+use tox::util::json::{self, Json};
+
+static USAGE: &'static str = "\
+Usage: tox-profile <profile> <command> [args]
+
+Commands:
+    id                          print the Tox ID
+    friends                     list the friends
+    set-name <name>             change the name
+    set-status-message <msg>    change the status message
+    set-status <none|away|busy> change the user status
+    set-nospam [hex]            change the nospam, to a random one if omitted
+    remove-friend <key|number>  remove a friend
+    export-friends              print the friends as JSON
+    import-friends <file>       add the friends from a file written by export-friends
+    encrypt                     encrypt the profile or change its passphrase
+    decrypt                     store the profile unencrypted";
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        let _ = writeln!(&mut io::stderr(), "{}", USAGE);
+        env::set_exit_status(2);
+        return;
+    }
+    if let Err(e) = run(Path::new(&args[1]), &args[2], &args[3..]) {
+        let _ = writeln!(&mut io::stderr(), "tox-profile: {}", e);
+        env::set_exit_status(1);
+    }
+}
+
+/// An opened profile
+struct Profile {
+    save: SaveFile,
+    /// The passphrase if the profile is encrypted
+    passphrase: Option<String>,
+}
+
+fn read_passphrase(prompt: &str, var: &str) -> Result<String, String> {
+    if let Ok(pass) = env::var(var) {
+        return Ok(pass);
+    }
+    let _ = write!(&mut io::stderr(), "{}: ", prompt);
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(_) => Ok(line.trim_right_matches(|c| c == '\n' || c == '\r').to_string()),
+        Err(e) => Err(format!("cannot read passphrase: {}", e)),
+    }
+}
+
+fn open(path: &Path) -> Result<Profile, String> {
+    let mut data = Vec::new();
+    let res = File::open(path).and_then(|mut f| f.read_to_end(&mut data));
+    if let Err(e) = res {
+        return Err(format!("{}: {}", path.display(), e));
+    }
+    let mut passphrase = None;
+    if encryptsave::is_encrypted(&data) {
+        let pass = try!(read_passphrase("Passphrase", "TOX_PASSPHRASE"));
+        data = match encryptsave::decrypt(&data, &pass) {
+            Ok(data) => data,
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+        passphrase = Some(pass);
+    }
+    match SaveFile::parse(&data) {
+        Ok(save) => Ok(Profile { save: save, passphrase: passphrase }),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+fn write(path: &Path, profile: &Profile) -> Result<(), String> {
+    let data = profile.save.to_bytes();
+    let data = match profile.passphrase {
+        Some(ref pass) => match encryptsave::encrypt(&data, pass) {
+            Ok(data) => data,
+            Err(e) => return Err(e.to_string()),
+        },
+        None => data,
+    };
+    profile::write_atomic(path, &data, 1)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Formats seconds since the epoch as a UTC date
+fn format_time(secs: u64) -> String {
+    if secs == 0 {
+        return "never".to_string();
+    }
+    // Days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    let rem = secs % 86400;
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day, rem / 3600,
+            rem / 60 % 60, rem % 60)
+}
+
+fn status_name(status: FriendStatus) -> &'static str {
+    match status {
+        FriendStatus::Added     => "added",
+        FriendStatus::Requested => "requested",
+        FriendStatus::Confirmed => "confirmed",
+        FriendStatus::Online    => "confirmed",
+    }
+}
+
+fn parse_nospam(s: &str) -> Result<[u8; 4], String> {
+    let digits: Vec<u32> = s.chars().filter_map(|c| c.to_digit(16)).collect();
+    if s.len() != 8 || digits.len() != 8 {
+        return Err(format!("invalid nospam: {}", s));
+    }
+    let mut nospam = [0u8; 4];
+    for (i, pair) in digits.chunks(2).enumerate() {
+        nospam[i] = (pair[0] * 16 + pair[1]) as u8;
+    }
+    Ok(nospam)
+}
+
+fn export_friends(friends: &[Friend]) -> Json {
+    let list = friends.iter().map(|f| {
+        Json::Object(vec!(
+            ("public_key".to_string(), Json::String(format!("{}", f.public_key))),
+            ("name".to_string(), Json::String(f.name.to_string_lossy())),
+            ("status_message".to_string(),
+             Json::String(f.status_message.to_string_lossy())),
+            ("status".to_string(), Json::String(status_name(f.status).to_string())),
+            ("last_seen".to_string(), Json::Number(f.last_seen as f64)),
+        ))
+    }).collect();
+    Json::Object(vec!(("friends".to_string(), Json::Array(list))))
+}
+
+/// Adds the friends in `doc` that are not friends yet. Returns how many were added
+fn import_friends(save: &mut SaveFile, doc: &Json) -> Result<usize, String> {
+    let list = match doc.get("friends").and_then(|f| f.as_array()) {
+        Some(list) => list,
+        None => return Err("not a friend list".to_string()),
+    };
+    let mut added = 0;
+    for (i, entry) in list.iter().enumerate() {
+        let key = entry.get("public_key").and_then(|k| k.as_str())
+                       .and_then(|k| k.parse::<ClientId>().ok());
+        let key = match key {
+            Some(key) => key,
+            None => return Err(format!("friend {} has no valid public key", i)),
+        };
+        if save.friends.iter().any(|f| f.public_key == key) {
+            continue;
+        }
+        let string = |field: &str| {
+            let s = entry.get(field).and_then(|s| s.as_str()).unwrap_or("");
+            ToxString::from(s.to_string())
+        };
+        save.friends.push(Friend {
+            status: FriendStatus::Confirmed,
+            public_key: key,
+            request_message: ToxString::from(String::new()),
+            name: string("name"),
+            status_message: string("status_message"),
+            user_status: UserStatus::None,
+            request_nospam: [0; 4],
+            last_seen: entry.get("last_seen").and_then(|t| t.as_f64())
+                            .map(|t| t as u64).unwrap_or(0),
+        });
+        added += 1;
+    }
+    Ok(added)
+}
+
+fn run(path: &Path, cmd: &str, args: &[String]) -> Result<(), String> {
+    let expected = match cmd {
+        "id" | "friends" | "export-friends" | "encrypt" | "decrypt" => 0,
+        "set-name" | "set-status-message" | "set-status" | "remove-friend"
+            | "import-friends" => 1,
+        "set-nospam" if args.len() <= 1 => args.len(),
+        _ => return Err(format!("unknown command or wrong arguments: {}\n\n{}", cmd,
+                                USAGE)),
+    };
+    if args.len() != expected {
+        return Err(format!("wrong number of arguments\n\n{}", USAGE));
+    }
+
+    // Don't edit profiles that are in use
+    let _lock = match cmd {
+        "id" | "friends" | "export-friends" => None,
+        _ => match profile::lock(path) {
+            Ok(lock) => Some(lock),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        },
+    };
+
+    let mut profile = try!(open(path));
+    match cmd {
+        "id" => {
+            println!("{}", profile.save.address());
+            return Ok(());
+        },
+        "friends" => {
+            for (i, f) in profile.save.friends.iter().enumerate() {
+                println!("{:4} {} {:9} {} {}", i, f.public_key, status_name(f.status),
+                         format_time(f.last_seen), f.name);
+            }
+            return Ok(());
+        },
+        "export-friends" => {
+            println!("{}", export_friends(&profile.save.friends));
+            return Ok(());
+        },
+        "set-name" => {
+            // toxcore ignores longer names when loading
+            if args[0].len() > MAX_NAME_LENGTH {
+                return Err(format!("name longer than {} bytes", MAX_NAME_LENGTH));
+            }
+            profile.save.name = ToxString::from(args[0].clone());
+        },
+        "set-status-message" => {
+            if args[0].len() > MAX_STATUSMESSAGE_LENGTH {
+                return Err(format!("status message longer than {} bytes",
+                                   MAX_STATUSMESSAGE_LENGTH));
+            }
+            profile.save.status_message = ToxString::from(args[0].clone());
+        },
+        "set-status" => {
+            profile.save.status = match &args[0][..] {
+                "none" => UserStatus::None,
+                "away" => UserStatus::Away,
+                "busy" => UserStatus::Busy,
+                _ => return Err(format!("invalid status: {}", args[0])),
+            };
+        },
+        "set-nospam" => {
+            profile.save.nospam = match args.first() {
+                Some(hex) => try!(parse_nospam(hex)),
+                None => {
+                    let mut nospam = [0u8; 4];
+                    random_bytes(&mut nospam);
+                    nospam
+                },
+            };
+            println!("{}", profile.save.address());
+        },
+        "remove-friend" => {
+            let pos = match args[0].parse::<usize>() {
+                Ok(n) if n < profile.save.friends.len() => Some(n),
+                _ => args[0].parse::<ClientId>().ok().and_then(|key| {
+                    profile.save.friends.iter().position(|f| f.public_key == key)
+                }),
+            };
+            match pos {
+                Some(pos) => { profile.save.friends.remove(pos); },
+                None => return Err(format!("no such friend: {}", args[0])),
+            }
+        },
+        "import-friends" => {
+            let mut s = String::new();
+            let res = File::open(Path::new(&args[0])).and_then(|mut f| {
+                f.read_to_string(&mut s)
+            });
+            if let Err(e) = res {
+                return Err(format!("{}: {}", args[0], e));
+            }
+            let doc = match json::parse(&s) {
+                Some(doc) => doc,
+                None => return Err(format!("{}: invalid JSON", args[0])),
+            };
+            let added = try!(import_friends(&mut profile.save, &doc));
+            println!("added {} friends", added);
+        },
+        "encrypt" => {
+            let pass = try!(read_passphrase("New passphrase", "TOX_NEW_PASSPHRASE"));
+            if pass.len() == 0 {
+                return Err("empty passphrase".to_string());
+            }
+            profile.passphrase = Some(pass);
+        },
+        "decrypt" => profile.passphrase = None,
+        _ => unreachable!(),
+    }
+    write(path, &profile)
+}
@@ -0,0 +1,479 @@
+//! Loading bootstrap node lists and bootstrapping with health tracking.
+//!
+//! Node lists can be read in the JSON format published by the node list on the
+//! wiki (`{"nodes": [{"ipv4": .., "port": .., "public_key": ..}, ..]}`) or in a
+//! simple text format with one node per line:
+//!
+//! ```text
+//! # address port public_key [tcp_port,tcp_port,..]
+//! 192.254.75.98 33445 951C88B7E75C867418ACDB5D273821372BB5BD652740BCDF623A4FA293E75D2F
+//! ```
+//!
+//! `Bootstrapper` bootstraps from a random subset of the nodes, starts a new round
+//! if the instance doesn't connect in time and remembers which nodes worked. The
+//! health can be saved and loaded so that bad nodes are avoided on the next start.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::path::{Path};
+//! use tox::core::*;
+//! use tox::bootstrap::{self, Bootstrapper, Health};
+//!
+//! let nodes = bootstrap::load_nodes(Path::new("nodes.json")).unwrap();
+//! let health = Health::load(Path::new("health.txt")).unwrap_or(Health::new());
+//!
+//! let (tox, events) = ToxControl::new(ToxOptions::new()).unwrap();
+//! let mut boot = Bootstrapper::new(nodes, health).timeout(20);
+//! boot.bootstrap(&tox).unwrap();
+//! loop {
+//!     boot.poll(&tox).unwrap();
+//!     // handle events
+//! #   break;
+//! }
+//! boot.health().save(Path::new("health.txt")).unwrap();
+//! ```
+
+use std::{error, fmt, io};
+use std::collections::{HashMap};
+use std::fs::{File};
+use std::io::{Read, Write};
+use std::path::{Path};
+
+use core::{ToxControl, BootstrapNode, BootstrapError, ClientId, Disconnected};
+use util::{random_bytes, unix_time};
+use util::json::{self, Json};
+
+/// An error that occurred while loading a node list
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file could not be read
+    Io(io::Error),
+    /// The file is not valid JSON or does not contain a node list
+    Json,
+    /// The node with the index is malformed
+    InvalidNode(usize),
+}
+
+impl error::Error for LoadError {
+    fn description(&self) -> &str {
+        match *self {
+            LoadError::Io(ref e) => e.description(),
+            LoadError::Json => "not a valid node list",
+            LoadError::InvalidNode(_) => "malformed node",
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Io(ref e) => write!(fmt, "{}", e),
+            LoadError::Json => fmt.write_str("not a valid node list"),
+            LoadError::InvalidNode(i) => write!(fmt, "malformed node at index {}", i),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> LoadError {
+        LoadError::Io(e)
+    }
+}
+
+fn read_file(path: &Path) -> io::Result<String> {
+    let mut file = try!(File::open(path));
+    let mut s = String::new();
+    try!(file.read_to_string(&mut s));
+    Ok(s)
+}
+
+/// Load a node list from a file. The format is detected automatically
+pub fn load_nodes(path: &Path) -> Result<Vec<BootstrapNode>, LoadError> {
+    let s = try!(read_file(path));
+    match s.trim_left().starts_with("{") {
+        true => parse_json(&s),
+        false => parse_text(&s),
+    }
+}
+
+fn json_node(node: &Json) -> Option<BootstrapNode> {
+    // Nodes that are known to be down are skipped
+    let udp = node.get("status_udp").and_then(|s| s.as_bool()).unwrap_or(true);
+    let tcp = node.get("status_tcp").and_then(|s| s.as_bool()).unwrap_or(true);
+    if !udp && !tcp {
+        return None;
+    }
+    let address = match node.get("ipv4").and_then(|a| a.as_str()) {
+        Some(a) if a != "-" && a.len() > 0 => a,
+        _ => match node.get("ipv6").and_then(|a| a.as_str()) {
+            Some(a) if a != "-" && a.len() > 0 => a,
+            _ => return None,
+        },
+    };
+    let port = match node.get("port").and_then(|p| p.as_f64()) {
+        Some(p) if p > 0.0 && p < 65536.0 => p as u16,
+        _ => return None,
+    };
+    let key = match node.get("public_key").and_then(|k| k.as_str()) {
+        Some(k) => match k.parse::<ClientId>() {
+            Ok(k) => k,
+            Err(_) => return None,
+        },
+        None => return None,
+    };
+    let mut tcp_ports = Vec::new();
+    if tcp {
+        if let Some(ports) = node.get("tcp_ports").and_then(|p| p.as_array()) {
+            for p in ports.iter().filter_map(|p| p.as_f64()) {
+                if p > 0.0 && p < 65536.0 {
+                    tcp_ports.push(p as u16);
+                }
+            }
+        }
+    }
+    Some(BootstrapNode {
+        address: address.to_string(),
+        udp_port: port,
+        tcp_ports: tcp_ports,
+        public_key: key,
+    })
+}
+
+/// Parse a node list in the JSON format. Nodes that are marked as offline are
+/// skipped
+pub fn parse_json(s: &str) -> Result<Vec<BootstrapNode>, LoadError> {
+    let doc = match json::parse(s) {
+        Some(doc) => doc,
+        None => return Err(LoadError::Json),
+    };
+    let list = match doc.get("nodes").and_then(|n| n.as_array()) {
+        Some(list) => list,
+        None => return Err(LoadError::Json),
+    };
+    let mut nodes = Vec::new();
+    for (i, node) in list.iter().enumerate() {
+        let online = ["status_udp", "status_tcp"].iter().any(|s| {
+            node.get(s).and_then(|s| s.as_bool()).unwrap_or(true)
+        });
+        match json_node(node) {
+            Some(node) => nodes.push(node),
+            None if online => return Err(LoadError::InvalidNode(i)),
+            None => { },
+        }
+    }
+    Ok(nodes)
+}
+
+/// Parse a node list in the text format. Empty lines and everything after a `#`
+/// is ignored. The index of an invalid node is its line number starting at 0
+pub fn parse_text(s: &str) -> Result<Vec<BootstrapNode>, LoadError> {
+    let mut nodes = Vec::new();
+    for (i, line) in s.lines().enumerate() {
+        let line = match line.find('#') {
+            Some(pos) => &line[..pos],
+            None => line,
+        };
+        let fields: Vec<&str> = line.split(|c: char| c.is_whitespace())
+                                    .filter(|f| f.len() > 0).collect();
+        if fields.len() == 0 {
+            continue;
+        }
+        if fields.len() < 3 || fields.len() > 4 {
+            return Err(LoadError::InvalidNode(i));
+        }
+        let port = match fields[1].parse() {
+            Ok(p) => p,
+            Err(_) => return Err(LoadError::InvalidNode(i)),
+        };
+        let key = match fields[2].parse() {
+            Ok(k) => k,
+            Err(_) => return Err(LoadError::InvalidNode(i)),
+        };
+        let mut tcp_ports = Vec::new();
+        if fields.len() == 4 {
+            for p in fields[3].split(',') {
+                match p.parse() {
+                    Ok(p) => tcp_ports.push(p),
+                    Err(_) => return Err(LoadError::InvalidNode(i)),
+                }
+            }
+        }
+        nodes.push(BootstrapNode {
+            address: fields[0].to_string(),
+            udp_port: port,
+            tcp_ports: tcp_ports,
+            public_key: key,
+        });
+    }
+    Ok(nodes)
+}
+
+/// How often bootstrapping from each node has succeeded and failed
+///
+/// Nodes are identified by their public key.
+#[derive(Clone, Debug)]
+pub struct Health {
+    /// `key -> (successes, failures)`
+    stats: HashMap<String, (u32, u32)>,
+}
+
+impl Health {
+    /// Create an empty record
+    #[inline]
+    pub fn new() -> Health {
+        Health { stats: HashMap::new() }
+    }
+
+    /// Load the record from a file written by `save`. Malformed lines are ignored
+    pub fn load(path: &Path) -> io::Result<Health> {
+        let s = try!(read_file(path));
+        let mut health = Health::new();
+        for line in s.lines() {
+            let fields: Vec<&str> = line.split(' ').collect();
+            if fields.len() != 3 {
+                continue;
+            }
+            if let (Ok(s), Ok(f)) = (fields[1].parse(), fields[2].parse()) {
+                health.stats.insert(fields[0].to_string(), (s, f));
+            }
+        }
+        Ok(health)
+    }
+
+    /// Write the record to a file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut file = try!(File::create(path));
+        for (key, &(s, f)) in self.stats.iter() {
+            try!(write!(file, "{} {} {}\n", key, s, f));
+        }
+        Ok(())
+    }
+
+    /// Returns `(successes, failures)` of the node
+    pub fn get(&self, node: &BootstrapNode) -> (u32, u32) {
+        let key = format!("{}", node.public_key);
+        self.stats.get(&key).map(|&s| s).unwrap_or((0, 0))
+    }
+
+    /// Returns the estimated probability that bootstrapping from the node works
+    pub fn score(&self, node: &BootstrapNode) -> f64 {
+        let (s, f) = self.get(node);
+        (s as f64 + 1.0) / (s as f64 + f as f64 + 2.0)
+    }
+
+    fn record(&mut self, node: &BootstrapNode, success: bool) {
+        let key = format!("{}", node.public_key);
+        let (mut s, mut f) = self.stats.get(&key).map(|&s| s).unwrap_or((0, 0));
+        match success {
+            true => s += 1,
+            false => f += 1,
+        }
+        self.stats.insert(key, (s, f));
+    }
+}
+
+/// Returns a random number in `[0, 1)`
+fn random_f64() -> f64 {
+    let mut buf = [0u8; 4];
+    random_bytes(&mut buf);
+    let n = buf.iter().fold(0u32, |n, &b| (n << 8) | b as u32);
+    n as f64 / 4294967296.0
+}
+
+/// Bootstraps a tox instance and tracks the health of the nodes
+pub struct Bootstrapper {
+    nodes: Vec<BootstrapNode>,
+    health: Health,
+    count: usize,
+    timeout: u64,
+    /// Indices of the nodes used in the current round
+    round: Vec<usize>,
+    /// Start of the current round
+    started: u64,
+    connected: bool,
+}
+
+impl Bootstrapper {
+    /// Create a bootstrapper that uses 4 nodes per round and a timeout of 10
+    /// seconds
+    pub fn new(nodes: Vec<BootstrapNode>, health: Health) -> Bootstrapper {
+        Bootstrapper {
+            nodes: nodes,
+            health: health,
+            count: 4,
+            timeout: 10,
+            round: Vec::new(),
+            started: 0,
+            connected: false,
+        }
+    }
+
+    /// Bootstrap from `count` nodes per round
+    #[inline]
+    pub fn nodes_per_round(mut self, count: usize) -> Bootstrapper {
+        self.count = count;
+        self
+    }
+
+    /// Start a new round if the instance is not connected after `timeout` seconds
+    #[inline]
+    pub fn timeout(mut self, timeout: u64) -> Bootstrapper {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the health record, including the results of this session
+    #[inline]
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    /// Pick nodes at random, preferring healthy ones
+    fn select(&self) -> Vec<usize> {
+        let mut left: Vec<usize> = (0..self.nodes.len()).collect();
+        let mut picked = Vec::new();
+        while picked.len() < self.count && left.len() > 0 {
+            let weights: Vec<f64> = left.iter()
+                                        .map(|&i| self.health.score(&self.nodes[i]))
+                                        .collect();
+            let total = weights.iter().fold(0.0, |a, &w| a + w);
+            let mut target = random_f64() * total;
+            let mut pos = left.len() - 1;
+            for (j, &w) in weights.iter().enumerate() {
+                if target < w {
+                    pos = j;
+                    break;
+                }
+                target -= w;
+            }
+            picked.push(left.swap_remove(pos));
+        }
+        picked
+    }
+
+    /// Start a new round of bootstrapping. Nodes whose address cannot be resolved
+    /// are counted as failed immediately
+    pub fn bootstrap(&mut self, tox: &ToxControl) -> Result<(), Disconnected> {
+        self.round.clear();
+        self.started = unix_time();
+        for i in self.select() {
+            let node = self.nodes[i].clone();
+            match tox.bootstrap(Box::new(node)) {
+                Ok(()) => self.round.push(i),
+                Err(BootstrapError::Disconnected) => return Err(Disconnected),
+                Err(_) => self.health.record(&self.nodes[i], false),
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the connection and start a new round if it's necessary. Should be
+    /// called regularly, e.g., once per second
+    pub fn poll(&mut self, tox: &ToxControl) -> Result<(), Disconnected> {
+        let connected = try!(tox.is_connected());
+        if connected && !self.connected {
+            for &i in self.round.iter() {
+                self.health.record(&self.nodes[i], true);
+            }
+            self.round.clear();
+        }
+        let lost = !connected && self.connected;
+        self.connected = connected;
+        if lost {
+            return self.bootstrap(tox);
+        }
+        if !connected && unix_time() >= self.started + self.timeout {
+            for &i in self.round.iter() {
+                self.health.record(&self.nodes[i], false);
+            }
+            return self.bootstrap(tox);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::{ClientId};
+    use super::{parse_json, parse_text, LoadError};
+
+    static KEY: &'static str =
+        "951C88B7E75C867418ACDB5D273821372BB5BD652740BCDF623A4FA293E75D2F";
+
+    #[test]
+    fn json() {
+        let s = format!(r#"{{"last_scan": 0, "nodes": [
+            {{"ipv4": "192.254.75.98", "ipv6": "-", "port": 33445,
+              "tcp_ports": [443, 3389], "public_key": "{0}",
+              "status_udp": true, "status_tcp": true}},
+            {{"ipv4": "-", "ipv6": "2607:5600:284::2", "port": 33445, "tcp_ports": [443],
+              "public_key": "{0}", "status_udp": true, "status_tcp": false}},
+            {{"ipv4": "1.2.3.4", "port": 1, "public_key": "broken",
+              "status_udp": false, "status_tcp": false}}
+        ]}}"#, KEY);
+        let nodes = parse_json(&s).unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].address, "192.254.75.98");
+        assert_eq!(nodes[0].udp_port, 33445);
+        assert_eq!(nodes[0].tcp_ports, vec!(443, 3389));
+        assert_eq!(nodes[0].public_key, KEY.parse::<ClientId>().unwrap());
+        assert_eq!(nodes[1].address, "2607:5600:284::2");
+        // TCP is down
+        assert_eq!(nodes[1].tcp_ports, vec!());
+    }
+
+    #[test]
+    fn json_malformed() {
+        let node = |fields: &str| format!(r#"{{"nodes": [{{"public_key": "{}",
+                                                          "ipv4": "1.2.3.4", "port": 1}},
+                                                        {{{}}}]}}"#, KEY, fields);
+        let invalid = |s: &str| match parse_json(s) {
+            Err(LoadError::InvalidNode(1)) => true,
+            _ => false,
+        };
+        assert!(invalid(&node(&format!(r#""ipv4": "1.2.3.4", "port": 1,
+                                         "public_key": "{}0""#, KEY))));
+        assert!(invalid(&node(&format!(r#""ipv4": "1.2.3.4", "port": 0,
+                                         "public_key": "{}""#, KEY))));
+        assert!(invalid(&node(&format!(r#""ipv4": "-", "port": 1,
+                                         "public_key": "{}""#, KEY))));
+        assert!(invalid(&node(r#""ipv4": "1.2.3.4", "port": 1"#)));
+        match parse_json(r#"{"nodes": 1}"#) {
+            Err(LoadError::Json) => { },
+            _ => panic!(),
+        }
+        match parse_json("[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[") {
+            Err(LoadError::Json) => { },
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn text() {
+        let s = format!("# comment\n\n192.254.75.98 33445 {0} 443,3389\n  \
+                         ::1\t1 {0} # local\n", KEY);
+        let nodes = parse_text(&s).unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].address, "192.254.75.98");
+        assert_eq!(nodes[0].udp_port, 33445);
+        assert_eq!(nodes[0].tcp_ports, vec!(443, 3389));
+        assert_eq!(nodes[1].address, "::1");
+        assert_eq!(nodes[1].udp_port, 1);
+        assert_eq!(nodes[1].tcp_ports, vec!());
+        assert_eq!(nodes[1].public_key, KEY.parse::<ClientId>().unwrap());
+    }
+
+    #[test]
+    fn text_malformed() {
+        let line = |l: &str| match parse_text(&format!("# nodes\n{}\n", l)) {
+            Err(LoadError::InvalidNode(1)) => true,
+            _ => false,
+        };
+        assert!(line("1.2.3.4 33445"));
+        assert!(line(&format!("1.2.3.4 70000 {}", KEY)));
+        assert!(line(&format!("1.2.3.4 1 {}00", KEY)));
+        assert!(line(&format!("1.2.3.4 1 {} 443,x", KEY)));
+        assert!(line(&format!("1.2.3.4 1 {} 443 1", KEY)));
+    }
+}
@@ -6,6 +6,9 @@ use std::raw::{Slice};
 use std::mem::{transmute};
 use std::time::{Duration};
 use std::path::{PathBuf};
+use std::ops::{Range};
+use std::collections::{HashMap, VecDeque};
+use std::thread::{JoinHandle};
 use std::ffi::{OsStr};
 use std::os::unix::{OsStrExt, OsStringExt};
 
@@ -14,101 +17,238 @@ use comm::{self, spsc};
 use core::ll::*;
 use core::{Address, ClientId, Event, ConnectionStatus,
            UserStatus, ChatChange, ControlType, Faerr, TransferType, AvatarFormat,
-           MAX_NAME_LENGTH, AVATAR_MAX_DATA_LENGTH, Hash, GroupchatType};
+           MAX_NAME_LENGTH, AVATAR_MAX_DATA_LENGTH, Hash, GroupchatType, PacketError,
+           MAX_CUSTOM_PACKET_SIZE, LOSSY_PACKET_ID_FIRST, LOSSY_PACKET_ID_LAST,
+           LOSSLESS_PACKET_ID_FIRST, LOSSLESS_PACKET_ID_LAST, BootstrapNode,
+           PublicKey, SecretKey, ID_CLIENT_SIZE, SECRET_KEY_SIZE, MAX_MESSAGE_LENGTH,
+           MAX_STATUSMESSAGE_LENGTH, MAX_FILENAME_LENGTH, FriendError, MessageError,
+           GroupError, FileError, AvatarError, BootstrapError, LoadError, ToxOptions,
+           OverflowPolicy, ToxString, FriendAddError};
 use core::Event::*;
 use core::ConnectionStatus::*;
 use core::TransferType::*;
 use av::{AvControl, AvEvents};
+use util::{self, unix_time};
+use encryptsave;
 
-use super::{ControlProducer, CoreEvents};
+use super::{ControlProducer, CoreEvents, AliveConsumer};
 
-use libc::{c_void, c_uint};
+use libc::{c_void, c_int, c_uint};
 
 type OneSpaceProducer<T> = spsc::one_space::Producer<'static, T>;
 
 pub enum Control {
     GetAddress(OneSpaceProducer<Address>),
-    AddFriend(Box<Address>, String, OneSpaceProducer<Result<i32, Faerr>>),
-    AddFriendNorequest(Box<ClientId>, OneSpaceProducer<Result<i32, ()>>),
-    GetFriendNumber(Box<ClientId>, OneSpaceProducer<Result<i32, ()>>),
-    GetClientId(i32, OneSpaceProducer<Result<Box<ClientId>, ()>>),
-    DelFriend(i32, OneSpaceProducer<Result<(),()>>),
-    GetFriendConnectionStatus(i32, OneSpaceProducer<Result<ConnectionStatus, ()>>),
+    AddFriend(Box<Address>, String, OneSpaceProducer<Result<i32, FriendAddError>>),
+    AddFriendNorequest(Box<ClientId>, OneSpaceProducer<Result<i32, FriendError>>),
+    GetFriendNumber(Box<ClientId>, OneSpaceProducer<Result<i32, FriendError>>),
+    GetClientId(i32, OneSpaceProducer<Result<Box<ClientId>, FriendError>>),
+    DelFriend(i32, OneSpaceProducer<Result<(), FriendError>>),
+    GetFriendConnectionStatus(i32, OneSpaceProducer<Result<ConnectionStatus, FriendError>>),
     FriendExists(i32, OneSpaceProducer<bool>),
-    SendMessage(i32, String, OneSpaceProducer<Result<u32, ()>>),
-    SendAction(i32, String, OneSpaceProducer<Result<u32, ()>>),
-    SetName(String, OneSpaceProducer<Result<(),()>>),
-    GetSelfName(OneSpaceProducer<Result<String, ()>>),
-    GetName(i32, OneSpaceProducer<Result<String, ()>>),
-    SetStatusMessage(String, OneSpaceProducer<Result<(), ()>>),
-    SetUserStatus(UserStatus, OneSpaceProducer<Result<(), ()>>),
-    GetStatusMessage(i32, OneSpaceProducer<Result<String, ()>>),
-    GetSelfStatusMessage(OneSpaceProducer<Result<String, ()>>),
-    GetUserStatus(i32, OneSpaceProducer<Result<UserStatus, ()>>),
-    GetSelfUserStatus(OneSpaceProducer<Result<UserStatus, ()>>),
-    GetLastOnline(i32, OneSpaceProducer<Result<u64, ()>>),
-    SetUserIsTyping(i32, bool, OneSpaceProducer<Result<(), ()>>),
+    SendMessage(i32, String, OneSpaceProducer<Result<u32, MessageError>>),
+    SendAction(i32, String, OneSpaceProducer<Result<u32, MessageError>>),
+    SetName(String, OneSpaceProducer<Result<(), MessageError>>),
+    GetSelfName(OneSpaceProducer<Result<ToxString, MessageError>>),
+    GetName(i32, OneSpaceProducer<Result<ToxString, FriendError>>),
+    SetStatusMessage(String, OneSpaceProducer<Result<(), MessageError>>),
+    SetUserStatus(UserStatus, OneSpaceProducer<Result<(), MessageError>>),
+    GetStatusMessage(i32, OneSpaceProducer<Result<ToxString, FriendError>>),
+    GetSelfStatusMessage(OneSpaceProducer<Result<ToxString, MessageError>>),
+    GetUserStatus(i32, OneSpaceProducer<Result<UserStatus, FriendError>>),
+    GetSelfUserStatus(OneSpaceProducer<Result<UserStatus, MessageError>>),
+    GetLastOnline(i32, OneSpaceProducer<Result<u64, FriendError>>),
+    SetUserIsTyping(i32, bool, OneSpaceProducer<Result<(), FriendError>>),
     GetIsTyping(i32, OneSpaceProducer<bool>),
     CountFriendlist(OneSpaceProducer<u32>),
     GetNumOnlineFriends(OneSpaceProducer<u32>),
     GetFriendlist(OneSpaceProducer<Vec<i32>>),
     GetNospam(OneSpaceProducer<[u8; 4]>),
     SetNospam([u8; 4]),
-    AddGroupchat(OneSpaceProducer<Result<i32, ()>>),
-    DelGroupchat(i32, OneSpaceProducer<Result<(), ()>>),
-    GroupPeername(i32, i32, OneSpaceProducer<Result<String, ()>>),
-    InviteFriend(i32, i32, OneSpaceProducer<Result<(), ()>>),
-    JoinGroupchat(i32, Vec<u8>, OneSpaceProducer<Result<i32, ()>>),
-    GroupMessageSend(i32, String, OneSpaceProducer<Result<(), ()>>),
-    GroupActionSend(i32, String, OneSpaceProducer<Result<(), ()>>),
-    GroupNumberPeers(i32, OneSpaceProducer<Result<i32, ()>>),
-    GroupGetNames(i32, OneSpaceProducer<Result<Vec<Option<String>>, ()>>),
+    GetKeys(OneSpaceProducer<(Box<PublicKey>, Box<SecretKey>)>),
+    AddGroupchat(OneSpaceProducer<Result<i32, GroupError>>),
+    DelGroupchat(i32, OneSpaceProducer<Result<(), GroupError>>),
+    GroupPeername(i32, i32, OneSpaceProducer<Result<ToxString, GroupError>>),
+    GroupPeerPublicKey(i32, i32, OneSpaceProducer<Result<Box<ClientId>, GroupError>>),
+    GroupPeerIsOurs(i32, i32, OneSpaceProducer<bool>),
+    InviteFriend(i32, i32, OneSpaceProducer<Result<(), GroupError>>),
+    JoinGroupchat(i32, Vec<u8>, OneSpaceProducer<Result<i32, GroupError>>),
+    GroupMessageSend(i32, String, OneSpaceProducer<Result<(), GroupError>>),
+    GroupActionSend(i32, String, OneSpaceProducer<Result<(), GroupError>>),
+    GroupSetTitle(i32, String, OneSpaceProducer<Result<(), GroupError>>),
+    GroupGetTitle(i32, OneSpaceProducer<Result<ToxString, GroupError>>),
+    GroupNumberPeers(i32, OneSpaceProducer<Result<i32, GroupError>>),
+    GroupGetNames(i32, OneSpaceProducer<Result<Vec<ToxString>, GroupError>>),
     CountChatlist(OneSpaceProducer<u32>),
-    GetChatlist(OneSpaceProducer<Vec<i32>>),
-    SetAvatar(AvatarFormat, Vec<u8>, OneSpaceProducer<Result<(), ()>>),
+    GetChatlist(OneSpaceProducer<Vec<(i32, GroupchatType)>>),
+    GroupGetType(i32, OneSpaceProducer<Result<GroupchatType, GroupError>>),
+    SetAvatar(AvatarFormat, Vec<u8>, OneSpaceProducer<Result<(), AvatarError>>),
     UnsetAvatar,
-    GetSelfAvatar(OneSpaceProducer<Result<(AvatarFormat, Vec<u8>, Hash), ()>>),
-    RequestAvatarInfo(i32, OneSpaceProducer<Result<(), ()>>),
-    RequestAvatarData(i32, OneSpaceProducer<Result<(), ()>>),
-    SendAvatarInfo(i32, OneSpaceProducer<Result<(), ()>>),
-    NewFileSender(i32, u64, PathBuf, OneSpaceProducer<Result<i32, ()>>),
-    FileSendControl(i32, TransferType, u8, u8, Vec<u8>, OneSpaceProducer<Result<(), ()>>),
-    FileSendData(i32, u8, Vec<u8>, OneSpaceProducer<Result<(), ()>>),
-    FileDataSize(i32, OneSpaceProducer<Result<i32, ()>>),
-    FileDataRemaining(i32, u8, TransferType, OneSpaceProducer<Result<u64, ()>>),
-    BootstrapFromAddress(String, u16, Box<ClientId>, OneSpaceProducer<Result<(), ()>>),
+    GetSelfAvatar(OneSpaceProducer<Result<(AvatarFormat, Vec<u8>, Hash), AvatarError>>),
+    RequestAvatarInfo(i32, OneSpaceProducer<Result<(), AvatarError>>),
+    RequestAvatarData(i32, OneSpaceProducer<Result<(), AvatarError>>),
+    SendAvatarInfo(i32, OneSpaceProducer<Result<(), AvatarError>>),
+    NewFileSender(i32, u64, PathBuf, OneSpaceProducer<Result<i32, FileError>>),
+    FileSendControl(i32, TransferType, u8, u8, Vec<u8>,
+                    OneSpaceProducer<Result<(), FileError>>),
+    FileSendData(i32, u8, Vec<u8>, OneSpaceProducer<Result<(), FileError>>),
+    FileDataSize(i32, OneSpaceProducer<Result<i32, FileError>>),
+    FileDataRemaining(i32, u8, TransferType, OneSpaceProducer<Result<u64, FileError>>),
+    RegisterLossyPackets(i32, Range<u8>, OneSpaceProducer<Result<(), PacketError>>),
+    RegisterLosslessPackets(i32, Range<u8>, OneSpaceProducer<Result<(), PacketError>>),
+    SendLossyPacket(i32, u8, Vec<u8>, OneSpaceProducer<Result<(), PacketError>>),
+    SendLosslessPacket(i32, u8, Vec<u8>, OneSpaceProducer<Result<(), PacketError>>),
+    BootstrapFromAddress(String, u16, Box<ClientId>,
+                         OneSpaceProducer<Result<(), BootstrapError>>),
+    AddTcpRelay(String, u16, Box<ClientId>, OneSpaceProducer<Result<(), BootstrapError>>),
+    Bootstrap(Box<BootstrapNode>, OneSpaceProducer<Result<(), BootstrapError>>),
     Isconnected(OneSpaceProducer<bool>),
+    DroppedEvents(OneSpaceProducer<u64>),
+    LastConnect(OneSpaceProducer<Option<u64>>),
+    LastDisconnect(OneSpaceProducer<Option<u64>>),
     Save(OneSpaceProducer<Vec<u8>>),
-    Load(Vec<u8>, OneSpaceProducer<Result<(), ()>>),
+    Load(Vec<u8>, OneSpaceProducer<Result<(), LoadError>>),
     Raw(OneSpaceProducer<*mut Tox>),
     Av(i32, OneSpaceProducer<Option<(AvControl, AvEvents)>>),
+    Shutdown(OneSpaceProducer<Vec<u8>>),
 }
 
 unsafe impl Send for Control { }
 
-pub struct Backend {
+struct AvSession {
+    /// Disconnected once the AV backend has been dropped
+    end: spsc::one_space::Consumer<'static, ()>,
+    /// Dropping this makes the AV backend stop
+    kill: spsc::one_space::Producer<'static, ()>,
+}
+
+/// A tox instance together with the state its callbacks need. The backend thread of
+/// `ToxControl` and the manually iterated `Tox` are built on top of it.
+pub struct Core {
     raw: *mut Tox,
     internal: Box<Internal>,
-    control: spsc::one_space::Consumer<'static, Control>,
-    av: Option<spsc::one_space::Consumer<'static, ()>>,
+    packet_handlers: HashMap<(i32, u8), Box<PacketHandler>>,
+    /// Whether we were connected to the DHT after the last iteration
+    connected: bool,
+    /// Times of the last connect and disconnect in seconds since the epoch
+    last_connect: Option<u64>,
+    last_disconnect: Option<u64>,
 }
 
-unsafe impl Send for Backend { }
-
-impl Drop for Backend {
+impl Drop for Core {
     fn drop(&mut self) {
         unsafe { tox_kill(self.raw); }
     }
 }
 
-impl Backend {
-    fn get_address(&mut self) -> Address {
+pub struct Backend {
+    core: Core,
+    control: spsc::unbounded::Consumer<'static, Control>,
+    av: Option<AvSession>,
+    /// Dropped once calls are no longer answered
+    alive: Option<OneSpaceProducer<()>>,
+}
+
+unsafe impl Send for Backend { }
+
+impl Core {
+    pub fn new(opts: &mut Tox_Options, events: EventSink) -> Option<Core> {
+        let tox = unsafe { tox_new(opts) };
+        if tox.is_null() {
+            return None;
+        }
+        let mut internal = Box::new(Internal { stop: false, dropped: 0, events: events });
+
+        unsafe {
+            let ip = &mut *internal as *mut _ as *mut c_void;
+            tox_callback_friend_request(        tox, Some(on_friend_request),        ip);
+            tox_callback_friend_message(        tox, Some(on_friend_message),        ip);
+            tox_callback_friend_action(         tox, Some(on_friend_action),         ip);
+            tox_callback_name_change(           tox, Some(on_name_change),           ip);
+            tox_callback_status_message(        tox, Some(on_status_message),        ip);
+            tox_callback_user_status(           tox, Some(on_user_status),           ip);
+            tox_callback_typing_change(         tox, Some(on_typing_change),         ip);
+            tox_callback_read_receipt(          tox, Some(on_read_receipt),          ip);
+            tox_callback_connection_status(     tox, Some(on_connection_status),     ip);
+            tox_callback_group_invite(          tox, Some(on_group_invite),          ip);
+            tox_callback_group_message(         tox, Some(on_group_message),         ip);
+            tox_callback_group_action(          tox, Some(on_group_action),          ip);
+            tox_callback_group_namelist_change( tox, Some(on_group_namelist_change), ip);
+            tox_callback_group_title(           tox, Some(on_group_title),           ip);
+            tox_callback_file_send_request(     tox, Some(on_file_send_request),     ip);
+            tox_callback_file_control(          tox, Some(on_file_control),          ip);
+            tox_callback_file_data(             tox, Some(on_file_data),             ip);
+            tox_callback_avatar_info(           tox, Some(on_avatar_info),           ip);
+            tox_callback_avatar_data(           tox, Some(on_avatar_data),           ip);
+        }
+        Some(Core {
+            raw: tox,
+            internal: internal,
+            packet_handlers: HashMap::new(),
+            connected: false,
+            last_connect: None,
+            last_disconnect: None,
+        })
+    }
+
+    /// Runs one iteration of the tox loop. The events it produces go to the sink
+    pub fn iterate(&mut self) {
+        unsafe { tox_do(self.raw); }
+        let connected = self.is_connected();
+        if connected != self.connected {
+            self.connected = connected;
+            let status = match connected {
+                true => {
+                    self.last_connect = Some(unix_time());
+                    Online
+                },
+                false => {
+                    self.last_disconnect = Some(unix_time());
+                    Offline
+                },
+            };
+            self.internal.send(SelfConnectionStatus(status));
+        }
+        self.internal.flush();
+    }
+
+    /// Returns how many milliseconds to wait until the next call to `iterate`
+    pub fn iteration_interval(&self) -> u32 {
+        unsafe { tox_do_interval(self.raw) }
+    }
+
+    pub fn raw(&self) -> *mut Tox {
+        self.raw
+    }
+
+    pub fn dropped_events(&self) -> u64 {
+        self.internal.dropped
+    }
+
+    pub fn last_connect(&self) -> Option<u64> {
+        self.last_connect
+    }
+
+    pub fn last_disconnect(&self) -> Option<u64> {
+        self.last_disconnect
+    }
+
+    /// Returns the events that were queued since the last call. Always empty if the
+    /// events go to a channel
+    pub fn take_events(&mut self) -> Vec<Event> {
+        match self.internal.events {
+            EventSink::Queue(ref mut queue) => std::mem::replace(queue, Vec::new()),
+            EventSink::Channel { .. } => Vec::new(),
+        }
+    }
+
+    pub fn get_address(&mut self) -> Address {
         let mut adr: Address = unsafe { std::mem::uninitialized() };
         unsafe { tox_get_address(&*self.raw, &mut adr as *mut _ as *mut _); }
         adr
     }
 
-    fn add_friend(&mut self, address: Box<Address>, msg: String) -> Result<i32, Faerr> {
+    pub fn add_friend(&mut self, address: Box<Address>, msg: String) -> Result<i32, Faerr> {
         let res = unsafe {
             tox_add_friend(self.raw, &*address as *const _ as *const _,
                            msg.as_bytes().as_ptr(), msg.len() as u16)
@@ -127,91 +267,117 @@ impl Backend {
         }
     }
 
-    fn add_friend_norequest(&mut self, client_id: Box<ClientId>) -> Result<i32, ()> {
+    pub fn add_friend_norequest(&mut self,
+                            client_id: Box<ClientId>) -> Result<i32, FriendError> {
         match unsafe { tox_add_friend_norequest(self.raw, client_id.raw.as_ptr()) } {
-            -1 => Err(()),
+            -1 => Err(FriendError::AddFailed),
             n => Ok(n),
         }
     }
 
-    fn get_friend_number(&mut self, client_id: Box<ClientId>) -> Result<i32, ()> {
+    pub fn get_friend_number(&mut self,
+                             client_id: Box<ClientId>) -> Result<i32, FriendError> {
         let res = unsafe {
             tox_get_friend_number(&*self.raw, client_id.raw.as_ptr())
         };
         match res {
-            -1 => Err(()),
+            -1 => Err(FriendError::NotFound),
             n => Ok(n),
         }
     }
 
-    fn get_client_id(&mut self, friendnumber: i32) -> Result<Box<ClientId>, ()> {
+    pub fn get_client_id(&mut self,
+                         friendnumber: i32) -> Result<Box<ClientId>, FriendError> {
         let mut client: ClientId = unsafe { std::mem::uninitialized() };
         let res = unsafe {
             tox_get_client_id(&*self.raw, friendnumber, client.raw.as_mut_ptr())
         };
         match res {
-            -1 => Err(()),
+            -1 => Err(FriendError::NotFound),
             _ => Ok(Box::new(client)),
         }
     }
 
-    fn del_friend(&mut self, friendnumber: i32) -> Result<(),()> {
+    pub fn del_friend(&mut self, friendnumber: i32) -> Result<(), FriendError> {
         match unsafe { tox_del_friend(self.raw, friendnumber) } {
-            -1 => Err(()),
-            _ => Ok(()),
+            -1 => return Err(FriendError::NotFound),
+            _ => { },
+        }
+        // toxcore forgot the handlers with the friend. They must not be used for a
+        // new friend with the same number
+        let keys: Vec<_> = self.packet_handlers.keys()
+                                               .filter(|k| k.0 == friendnumber)
+                                               .cloned().collect();
+        for key in keys.iter() {
+            self.packet_handlers.remove(key);
         }
+        Ok(())
     }
 
-    fn get_friend_connection_status(
+    pub fn get_friend_connection_status(
             &mut self,
-            friendnumber: i32) -> Result<ConnectionStatus, ()> {
+            friendnumber: i32) -> Result<ConnectionStatus, FriendError> {
         match unsafe { tox_get_friend_connection_status(&*self.raw, friendnumber) } {
             1 => Ok(Online),
             0 => Ok(Offline),
-            _ => Err(()),
+            _ => Err(FriendError::NotFound),
         }
     }
 
-    fn friend_exists(&mut self, friendnumber: i32) -> bool {
+    pub fn friend_exists(&mut self, friendnumber: i32) -> bool {
         match unsafe { tox_friend_exists(&*self.raw, friendnumber) } {
             1 => true,
             _ => false,
         }
     }
 
-    fn send_message(&mut self, friendnumber: i32, mut msg: String) -> Result<u32, ()> {
+    pub fn send_message(&mut self, friendnumber: i32,
+                    mut msg: String) -> Result<u32, MessageError> {
+        if msg.len() > MAX_MESSAGE_LENGTH {
+            return Err(MessageError::TooLong);
+        }
         let res = unsafe {
             tox_send_message(self.raw, friendnumber,
                              msg.as_mut_vec().as_ptr(), msg.len() as u32)
         };
         match res {
-            0 => Err(()),
+            0 => Err(MessageError::NotSent),
             n => Ok(n),
         }
     }
 
-    fn send_action(&mut self, friendnumber: i32, mut action: String) -> Result<u32, ()> {
+    pub fn send_action(&mut self, friendnumber: i32,
+                   mut action: String) -> Result<u32, MessageError> {
+        if action.len() > MAX_MESSAGE_LENGTH {
+            return Err(MessageError::TooLong);
+        }
         let res = unsafe {
             tox_send_action(self.raw, friendnumber,
                             action.as_mut_vec().as_ptr(), action.len() as u32)
         };
         match res {
-            0 => Err(()),
+            0 => Err(MessageError::NotSent),
             n => Ok(n),
         }
     }
 
-    fn set_name(&mut self, mut name: String) -> Result<(),()> {
+    pub fn set_name(&mut self, mut name: String) -> Result<(), MessageError> {
+        if name.len() > MAX_NAME_LENGTH {
+            return Err(MessageError::TooLong);
+        }
+        if name.len() == 0 {
+            return Err(MessageError::Empty);
+        }
         let res = unsafe {
             tox_set_name(self.raw, name.as_mut_vec().as_ptr(), name.len() as u16)
         };
         match res {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(MessageError::Failed),
         }
     }
 
-    fn get_self_name(&mut self) -> Result<String, ()> {
+    pub fn get_self_name(&mut self) -> Result<ToxString, MessageError> {
         let mut name = Vec::with_capacity(MAX_NAME_LENGTH);
         let res = unsafe {
             let len = tox_get_self_name(&*self.raw, name.as_mut_ptr());
@@ -219,15 +385,12 @@ impl Backend {
             len
         };
         match res {
-            0 => Err(()),
-            _ => match String::from_utf8(name) {
-                Ok(name) => Ok(name),
-                _ => Err(()),
-            },
+            0 => Err(MessageError::Empty),
+            _ => Ok(ToxString::from_bytes(name)),
         }
     }
 
-    fn get_name(&mut self, friendnumber: i32) -> Result<String, ()> {
+    pub fn get_name(&mut self, friendnumber: i32) -> Result<ToxString, FriendError> {
         let mut name = Vec::with_capacity(MAX_NAME_LENGTH);
         let res = unsafe {
             let len = tox_get_name(&*self.raw, friendnumber, name.as_mut_ptr());
@@ -236,36 +399,37 @@ impl Backend {
             len
         };
         match res {
-            -1 => Err(()),
-            _ => match String::from_utf8(name) {
-                Ok(name) => Ok(name),
-                _ => Err(()),
-            },
+            -1 => Err(FriendError::NotFound),
+            _ => Ok(ToxString::from_bytes(name)),
         }
     }
 
-    fn set_status_message(&mut self, mut status: String) -> Result<(),()> {
+    pub fn set_status_message(&mut self, mut status: String) -> Result<(), MessageError> {
+        if status.len() > MAX_STATUSMESSAGE_LENGTH {
+            return Err(MessageError::TooLong);
+        }
         let res = unsafe {
             tox_set_status_message(self.raw, status.as_mut_vec().as_ptr(),
                                    status.len() as u16)
         };
         match res {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(MessageError::Failed),
         }
     }
 
-    fn set_user_status(&mut self, userstatus: UserStatus) -> Result<(), ()> {
+    pub fn set_user_status(&mut self, userstatus: UserStatus) -> Result<(), MessageError> {
         match unsafe { tox_set_user_status(self.raw, userstatus as u8) } {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(MessageError::Failed),
         }
     }
 
-    fn get_status_message(&mut self, friendnumber: i32) -> Result<String, ()> {
+    pub fn get_status_message(&mut self,
+                              friendnumber: i32) -> Result<ToxString, FriendError> {
         let size = unsafe { tox_get_status_message_size(&*self.raw, friendnumber) };
         let size = match size {
-            -1 => return Err(()),
+            -1 => return Err(FriendError::NotFound),
             _ => size,
         };
         let mut status = Vec::with_capacity(size as usize);
@@ -276,18 +440,15 @@ impl Backend {
             len
         };
         match size {
-            -1 => return Err(()),
-            _ => match String::from_utf8(status) {
-                Ok(status) => Ok(status),
-                _ => return Err(()),
-            },
+            -1 => Err(FriendError::NotFound),
+            _ => Ok(ToxString::from_bytes(status)),
         }
     }
 
-    fn get_self_status_message(&mut self) -> Result<String, ()> {
+    pub fn get_self_status_message(&mut self) -> Result<ToxString, MessageError> {
         let size = unsafe { tox_get_self_status_message_size(&*self.raw) };
         let size = match size {
-            -1 => return Err(()),
+            -1 => return Err(MessageError::Failed),
             _ => size as u32,
         };
         let mut status = Vec::with_capacity(size as usize);
@@ -297,66 +458,64 @@ impl Backend {
             len
         };
         match size {
-            -1 => return Err(()),
-            _ => match String::from_utf8(status) {
-                Ok(status) => Ok(status),
-                _ => return Err(()),
-            },
+            -1 => Err(MessageError::Failed),
+            _ => Ok(ToxString::from_bytes(status)),
         }
     }
 
-    fn get_user_status(&mut self, friendnumber: i32) -> Result<UserStatus, ()> {
+    pub fn get_user_status(&mut self,
+                           friendnumber: i32) -> Result<UserStatus, FriendError> {
         match unsafe { tox_get_user_status(&*self.raw, friendnumber) as u32 } {
             TOX_USERSTATUS_AWAY => Ok(UserStatus::Away),
             TOX_USERSTATUS_NONE => Ok(UserStatus::None),
             TOX_USERSTATUS_BUSY => Ok(UserStatus::Busy),
-            _ => Err(())
+            _ => Err(FriendError::NotFound)
         }
     }
 
-    fn get_self_user_status(&mut self) -> Result<UserStatus, ()> {
+    pub fn get_self_user_status(&mut self) -> Result<UserStatus, MessageError> {
         match unsafe { tox_get_self_user_status(&*self.raw) as u32 } {
             TOX_USERSTATUS_AWAY => Ok(UserStatus::Away),
             TOX_USERSTATUS_NONE => Ok(UserStatus::None),
             TOX_USERSTATUS_BUSY => Ok(UserStatus::Busy),
-            _ => Err(())
+            _ => Err(MessageError::Failed)
         }
     }
 
-    fn get_last_online(&mut self, friendnumber: i32) -> Result<u64, ()> {
+    pub fn get_last_online(&mut self, friendnumber: i32) -> Result<u64, FriendError> {
         match unsafe { tox_get_last_online(&*self.raw, friendnumber) } {
-            -1 => Err(()),
+            -1 => Err(FriendError::NotFound),
             n => Ok(n),
         }
     }
 
-    fn set_user_is_typing(&mut self, friendnumber: i32,
-                              is_typing: bool) -> Result<(), ()> {
+    pub fn set_user_is_typing(&mut self, friendnumber: i32,
+                              is_typing: bool) -> Result<(), FriendError> {
         let raw = unsafe {
             tox_set_user_is_typing(self.raw, friendnumber, is_typing as u8)
         };
         match raw {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(FriendError::NotFound),
         }
     }
 
-    fn get_is_typing(&mut self, friendnumber: i32) -> bool {
+    pub fn get_is_typing(&mut self, friendnumber: i32) -> bool {
         match unsafe { tox_get_is_typing(&*self.raw, friendnumber) } {
             0 => false,
             _ => true,
         }
     }
 
-    fn count_friendlist(&mut self) -> u32 {
+    pub fn count_friendlist(&mut self) -> u32 {
         unsafe { tox_count_friendlist(&*self.raw) }
     }
 
-    fn get_num_online_friends(&mut self) -> u32 {
+    pub fn get_num_online_friends(&mut self) -> u32 {
         unsafe { tox_get_num_online_friends(&*self.raw) }
     }
 
-    fn get_friendlist(&mut self) -> Vec<i32> {
+    pub fn get_friendlist(&mut self) -> Vec<i32> {
         let size = self.count_friendlist();
         let mut vec = Vec::with_capacity(size as usize);
         unsafe {
@@ -366,30 +525,41 @@ impl Backend {
         vec
     }
 
-    fn get_nospam(&mut self) -> [u8; 4] {
+    pub fn get_nospam(&mut self) -> [u8; 4] {
         unsafe { std::mem::transmute(tox_get_nospam(&*self.raw).to_be()) }
     }
 
-    fn set_nospam(&mut self, nospam: [u8; 4]) {
+    pub fn set_nospam(&mut self, nospam: [u8; 4]) {
         unsafe { tox_set_nospam(self.raw, Int::from_be(std::mem::transmute(nospam))); }
     }
 
-    fn add_groupchat(&mut self) -> Result<i32, ()> {
+    pub fn get_keys(&mut self) -> (Box<PublicKey>, Box<SecretKey>) {
+        // Write the secret key directly into its final location so that no copies
+        // are left behind.
+        let mut public = Box::new(ClientId { raw: [0; ID_CLIENT_SIZE] });
+        let mut secret = Box::new(SecretKey { raw: [0; SECRET_KEY_SIZE] });
+        unsafe {
+            tox_get_keys(self.raw, public.raw.as_mut_ptr(), secret.raw.as_mut_ptr());
+        }
+        (public, secret)
+    }
+
+    pub fn add_groupchat(&mut self) -> Result<i32, GroupError> {
         match unsafe { tox_add_groupchat(self.raw) } {
-            -1 => Err(()),
+            -1 => Err(GroupError::Failed),
             n => Ok(n),
         }
     }
 
-    fn del_groupchat(&mut self, groupnumber: i32) -> Result<(),()> {
+    pub fn del_groupchat(&mut self, groupnumber: i32) -> Result<(), GroupError> {
         match unsafe { tox_del_groupchat(self.raw, groupnumber) } {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(GroupError::NotFound),
         }
     }
 
-    fn group_peername(&mut self, groupnumber: i32,
-                          peernumber: i32) -> Result<String, ()> {
+    pub fn group_peername(&mut self, groupnumber: i32,
+                          peernumber: i32) -> Result<ToxString, GroupError> {
         let mut vec = Vec::with_capacity(MAX_NAME_LENGTH);
         let len = unsafe {
             let len = tox_group_peername(&*self.raw, groupnumber, peernumber,
@@ -398,67 +568,123 @@ impl Backend {
             len
         };
         match len {
-            -1 => Err(()),
-            _ => match String::from_utf8(vec) {
-                Ok(name) => Ok(name),
-                _ => Err(()),
-            }
+            -1 => Err(GroupError::PeerNotFound),
+            _ => Ok(ToxString::from_bytes(vec)),
+        }
+    }
+
+    pub fn group_peer_public_key(&mut self, groupnumber: i32,
+                             peernumber: i32) -> Result<Box<ClientId>, GroupError> {
+        let mut client: ClientId = unsafe { std::mem::uninitialized() };
+        let res = unsafe {
+            tox_group_peer_pubkey(&*self.raw, groupnumber, peernumber,
+                                  client.raw.as_mut_ptr())
+        };
+        match res {
+            -1 => Err(GroupError::PeerNotFound),
+            _ => Ok(Box::new(client)),
         }
     }
 
-    fn invite_friend(&mut self, friendnumber: i32, groupnumber: i32) -> Result<(), ()> {
+    pub fn group_peer_is_ours(&mut self, groupnumber: i32, peernumber: i32) -> bool {
+        match unsafe { tox_group_peernumber_is_ours(&*self.raw, groupnumber, peernumber) } {
+            1 => true,
+            _ => false,
+        }
+    }
+
+    pub fn invite_friend(&mut self, friendnumber: i32,
+                     groupnumber: i32) -> Result<(), GroupError> {
         match unsafe { tox_invite_friend(self.raw, friendnumber, groupnumber) } {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(GroupError::InviteFailed),
         }
     }
 
-    fn join_groupchat(&mut self, friendnumber: i32,
-                      data: Vec<u8>) -> Result<i32, ()> {
+    pub fn join_groupchat(&mut self, friendnumber: i32,
+                      data: Vec<u8>) -> Result<i32, GroupError> {
         let res = unsafe {
             tox_join_groupchat(self.raw, friendnumber, data.as_ptr(), data.len() as u16)
         };
         match res {
-            -1 => Err(()),
+            -1 => Err(GroupError::JoinFailed),
             n => Ok(n),
         }
     }
 
-    fn group_message_send(&mut self, groupnumber: i32,
-                          mut msg: String) -> Result<(), ()> {
+    pub fn group_message_send(&mut self, groupnumber: i32,
+                          mut msg: String) -> Result<(), GroupError> {
+        if msg.len() > MAX_MESSAGE_LENGTH {
+            return Err(GroupError::TooLong);
+        }
         let res = unsafe {
             tox_group_message_send(self.raw, groupnumber, msg.as_mut_vec().as_ptr(),
                                    msg.len() as u16)
         };
         match res {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(GroupError::SendFailed),
         }
     }
 
-    fn group_action_send(&mut self, groupnumber: i32, mut act: String) -> Result<(), ()> {
+    pub fn group_action_send(&mut self, groupnumber: i32,
+                         mut act: String) -> Result<(), GroupError> {
+        if act.len() > MAX_MESSAGE_LENGTH {
+            return Err(GroupError::TooLong);
+        }
         let res = unsafe {
             tox_group_action_send(self.raw, groupnumber, act.as_mut_vec().as_ptr(),
                                   act.len() as u16)
         };
         match res {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(GroupError::SendFailed),
+        }
+    }
+
+    pub fn group_set_title(&mut self, groupnumber: i32,
+                       title: String) -> Result<(), GroupError> {
+        if title.len() > MAX_NAME_LENGTH {
+            return Err(GroupError::TooLong);
+        }
+        let res = unsafe {
+            tox_group_set_title(self.raw, groupnumber, title.as_bytes().as_ptr(),
+                                title.len() as u8)
+        };
+        match res {
+            0 => Ok(()),
+            _ => Err(GroupError::NotFound),
+        }
+    }
+
+    pub fn group_get_title(&mut self,
+                           groupnumber: i32) -> Result<ToxString, GroupError> {
+        let mut vec = Vec::with_capacity(MAX_NAME_LENGTH);
+        let len = unsafe {
+            let len = tox_group_get_title(&*self.raw, groupnumber, vec.as_mut_ptr(),
+                                          MAX_NAME_LENGTH as u32);
+            // len might be -1 but it doesn't matter if we don't return vec.
+            vec.set_len(len as usize);
+            len
+        };
+        match len {
+            -1 => Err(GroupError::NotFound),
+            _ => Ok(ToxString::from_bytes(vec)),
         }
     }
 
-    fn group_number_peers(&mut self, groupnumber: i32) -> Result<i32, ()> {
+    pub fn group_number_peers(&mut self, groupnumber: i32) -> Result<i32, GroupError> {
         match unsafe { tox_group_number_peers(&*self.raw, groupnumber) } {
-            -1 => Err(()),
+            -1 => Err(GroupError::NotFound),
             n => Ok(n),
         }
     }
 
-    fn group_get_names(&mut self,
-                           groupnumber: i32) -> Result<Vec<Option<String>>, ()> {
+    pub fn group_get_names(&mut self,
+                           groupnumber: i32) -> Result<Vec<ToxString>, GroupError> {
         let num = match self.group_number_peers(groupnumber) {
             Ok(n) => n as usize,
-            _ => return Err(()),
+            Err(e) => return Err(e),
         };
         let mut names = Vec::with_capacity(num);
         let mut lengths = Vec::with_capacity(num);
@@ -470,47 +696,63 @@ impl Backend {
             len
         };
         if len == -1 {
-            return Err(());
-        }
-        let mut real_names = Vec::with_capacity(len as usize);
-        for (name, &length) in names.iter().zip(lengths.iter()) {
-            match std::str::from_utf8(&name[..length as usize]) {
-                Ok(s) => real_names.push(Some(s.to_string())),
-                _ => real_names.push(None),
-            }
+            return Err(GroupError::NotFound);
         }
+        let real_names = names.iter().zip(lengths.iter()).map(|(name, &length)| {
+            ToxString::from_bytes(name[..length as usize].to_vec())
+        }).collect();
         Ok(real_names)
     }
 
-    fn count_chatlist(&mut self) -> u32 {
+    pub fn count_chatlist(&mut self) -> u32 {
         unsafe { tox_count_chatlist(&*self.raw) }
     }
 
-    fn get_chatlist(&mut self) -> Vec<i32> {
+    pub fn get_chatlist(&mut self) -> Vec<(i32, GroupchatType)> {
         let num = unsafe { tox_count_chatlist(&*self.raw) };
         let mut vec = Vec::with_capacity(num as usize);
         unsafe {
             let num = tox_get_chatlist(&*self.raw, vec.as_mut_ptr(), num);
             vec.set_len(num as usize);
         }
-        vec
+        let mut chats = Vec::with_capacity(vec.len());
+        for &group in vec.iter() {
+            if let Ok(ty) = self.group_get_type(group) {
+                chats.push((group, ty));
+            }
+        }
+        chats
+    }
+
+    pub fn group_get_type(&mut self,
+                          groupnumber: i32) -> Result<GroupchatType, GroupError> {
+        match unsafe { tox_group_get_type(&*self.raw, groupnumber) as c_uint } {
+            TOX_GROUPCHAT_TYPE_TEXT => Ok(GroupchatType::Text),
+            TOX_GROUPCHAT_TYPE_AV => Ok(GroupchatType::Av),
+            _ => Err(GroupError::NotFound),
+        }
     }
 
-    fn set_avatar(&mut self, format: AvatarFormat, data: Vec<u8>) -> Result<(), ()> {
+    pub fn set_avatar(&mut self, format: AvatarFormat,
+                  data: Vec<u8>) -> Result<(), AvatarError> {
+        if data.len() > AVATAR_MAX_DATA_LENGTH {
+            return Err(AvatarError::TooLong);
+        }
         let res = unsafe {
             tox_set_avatar(self.raw, format as u8, data.as_ptr(), data.len() as u32)
         };
         match res {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(AvatarError::Failed),
         }
     }
 
-    fn unset_avatar(&mut self) {
+    pub fn unset_avatar(&mut self) {
         unsafe { tox_unset_avatar(self.raw); }
     }
 
-    fn get_self_avatar(&mut self) -> Result<(AvatarFormat, Vec<u8>, Hash), ()> {
+    pub fn get_self_avatar(&mut self)
+                               -> Result<(AvatarFormat, Vec<u8>, Hash), AvatarError> {
         let mut data = Vec::with_capacity(AVATAR_MAX_DATA_LENGTH);
         let mut hash: Hash = unsafe { std::mem::uninitialized() };
         let mut format = 0;
@@ -520,64 +762,67 @@ impl Backend {
                                 AVATAR_MAX_DATA_LENGTH as u32, hash.hash.as_mut_ptr())
         };
         if res == -1 {
-            return Err(());
+            return Err(AvatarError::Failed);
         }
         unsafe { data.set_len(length as usize); }
         data.shrink_to_fit();
         let format = match format as c_uint {
             TOX_AVATAR_FORMAT_NONE => AvatarFormat::None,
             TOX_AVATAR_FORMAT_PNG => AvatarFormat::PNG,
-            _ => return Err(()),
+            _ => return Err(AvatarError::InvalidFormat),
         };
         Ok((format, data, hash))
     }
 
-    fn request_avatar_info(&self, friendnumber: i32) -> Result<(), ()> {
+    pub fn request_avatar_info(&self, friendnumber: i32) -> Result<(), AvatarError> {
         let res = unsafe {
             tox_request_avatar_info(self.raw, friendnumber)
         };
         match res {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(AvatarError::NotSent),
         }
     }
 
-    fn request_avatar_data(&self, friendnumber: i32) -> Result<(), ()> {
+    pub fn request_avatar_data(&self, friendnumber: i32) -> Result<(), AvatarError> {
         let res = unsafe {
             tox_request_avatar_data(self.raw, friendnumber)
         };
         match res {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(AvatarError::NotSent),
         }
     }
 
-    fn send_avatar_info(&mut self, friendnumber: i32) -> Result<(), ()> {
+    pub fn send_avatar_info(&mut self, friendnumber: i32) -> Result<(), AvatarError> {
         let res = unsafe {
             tox_send_avatar_info(self.raw, friendnumber)
         };
         match res {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(AvatarError::NotSent),
         }
     }
 
-    fn new_file_sender(&mut self, friendnumber: i32, filesize: u64,
-                       filename: PathBuf) -> Result<i32, ()> {
+    pub fn new_file_sender(&mut self, friendnumber: i32, filesize: u64,
+                       filename: PathBuf) -> Result<i32, FileError> {
         let filename = filename.into_os_string().into_vec();
+        if filename.len() > MAX_FILENAME_LENGTH {
+            return Err(FileError::TooLong);
+        }
         let res = unsafe {
             tox_new_file_sender(self.raw, friendnumber, filesize,
                                 filename.as_ptr(), filename.len() as u16)
         };
         match res {
-            -1 => Err(()),
+            -1 => Err(FileError::Failed),
             n => Ok(n)
         }
     }
 
-    fn file_send_control(&mut self, friendnumber: i32, send_receive: TransferType,
+    pub fn file_send_control(&mut self, friendnumber: i32, send_receive: TransferType,
                          filenumber: u8, message_id: u8,
-                         data: Vec<u8>) -> Result<(), ()> {
+                         data: Vec<u8>) -> Result<(), FileError> {
         let res = unsafe {
             tox_file_send_control(self.raw, friendnumber, 1 - send_receive as u8,
                                   filenumber, message_id, data.as_ptr(),
@@ -585,43 +830,108 @@ impl Backend {
         };
         match res {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(FileError::Failed),
         }
     }
 
-    fn file_send_data(&mut self, friendnumber: i32, filenumber: u8,
-                      data: Vec<u8>) -> Result<(), ()> {
+    pub fn file_send_data(&mut self, friendnumber: i32, filenumber: u8,
+                      data: Vec<u8>) -> Result<(), FileError> {
         let res = unsafe {
             tox_file_send_data(self.raw, friendnumber, filenumber, data.as_ptr(),
                                data.len() as u16)
         };
         match res {
             0 => Ok(()),
-            _ => Err(()),
+            _ => Err(FileError::Failed),
         }
     }
 
-    fn file_data_size(&mut self, friendnumber: i32) -> Result<i32, ()> {
+    pub fn file_data_size(&mut self, friendnumber: i32) -> Result<i32, FileError> {
         match unsafe { tox_file_data_size(&*self.raw, friendnumber) } {
-            -1 => Err(()),
+            -1 => Err(FileError::NotFound),
             n => Ok(n),
         }
     }
 
-    fn file_data_remaining(&mut self, friendnumber: i32, filenumber: u8,
-                               send_receive: TransferType) -> Result<u64, ()> {
+    pub fn file_data_remaining(&mut self, friendnumber: i32, filenumber: u8,
+                               send_receive: TransferType) -> Result<u64, FileError> {
         let res = unsafe {
             tox_file_data_remaining(&*self.raw, friendnumber, filenumber,
                                     send_receive as u8)
         };
         match res {
-            0 => Err(()),
+            0 => Err(FileError::Failed),
             n => Ok(n),
         }
     }
 
-    fn bootstrap_from_address(&mut self, mut address: String, port: u16,
-                              public_key: Box<ClientId>) -> Result<(), ()> {
+    pub fn register_packets(&mut self, friendnumber: i32, ids: Range<u8>,
+                        lossless: bool) -> Result<(), PacketError> {
+        let (first, last) = match lossless {
+            true => (LOSSLESS_PACKET_ID_FIRST, LOSSLESS_PACKET_ID_LAST),
+            false => (LOSSY_PACKET_ID_FIRST, LOSSY_PACKET_ID_LAST),
+        };
+        if ids.start < first || ids.end > last + 1 {
+            return Err(PacketError::InvalidId);
+        }
+        for id in ids {
+            let mut handler = Box::new(PacketHandler {
+                internal: &mut *self.internal,
+                friendnumber: friendnumber,
+                lossless: lossless,
+            });
+            let hp = &mut *handler as *mut _ as *mut c_void;
+            let res = unsafe {
+                match lossless {
+                    true => tox_lossless_packet_registerhandler(
+                                self.raw, friendnumber, id, Some(on_custom_packet), hp),
+                    false => tox_lossy_packet_registerhandler(
+                                self.raw, friendnumber, id, Some(on_custom_packet), hp),
+                }
+            };
+            if res != 0 {
+                return Err(PacketError::NoFriend);
+            }
+            // toxcore now points to the new handler so the old one can be freed
+            self.packet_handlers.insert((friendnumber, id), handler);
+        }
+        Ok(())
+    }
+
+    pub fn send_packet(&mut self, friendnumber: i32, id: u8, data: Vec<u8>,
+                   lossless: bool) -> Result<(), PacketError> {
+        let valid = match lossless {
+            true => id >= LOSSLESS_PACKET_ID_FIRST && id <= LOSSLESS_PACKET_ID_LAST,
+            false => id >= LOSSY_PACKET_ID_FIRST && id <= LOSSY_PACKET_ID_LAST,
+        };
+        if !valid {
+            return Err(PacketError::InvalidId);
+        }
+        if data.len() + 1 > MAX_CUSTOM_PACKET_SIZE {
+            return Err(PacketError::TooLong);
+        }
+        let mut packet = Vec::with_capacity(data.len() + 1);
+        packet.push(id);
+        packet.push_all(&data);
+        let res = unsafe {
+            match lossless {
+                true => tox_send_lossless_packet(&*self.raw, friendnumber, packet.as_ptr(),
+                                                 packet.len() as u32),
+                false => tox_send_lossy_packet(&*self.raw, friendnumber, packet.as_ptr(),
+                                               packet.len() as u32),
+            }
+        };
+        match res {
+            0 => Ok(()),
+            _ => Err(PacketError::SendFailed),
+        }
+    }
+
+    pub fn bootstrap_from_address(&mut self, mut address: String, port: u16,
+                              public_key: Box<ClientId>) -> Result<(), BootstrapError> {
+        if address.contains_char('\0') {
+            return Err(BootstrapError::InvalidAddress);
+        }
         let res = unsafe {
             address.as_mut_vec().push(0);
             tox_bootstrap_from_address(self.raw, address.as_bytes().as_ptr() as *const _,
@@ -629,241 +939,535 @@ impl Backend {
         };
         match res {
             1 => Ok(()),
-            _ => Err(()),
+            _ => Err(BootstrapError::ResolveFailed),
+        }
+    }
+
+    pub fn add_tcp_relay(&mut self, mut address: String, port: u16,
+                     public_key: Box<ClientId>) -> Result<(), BootstrapError> {
+        if address.contains_char('\0') {
+            return Err(BootstrapError::InvalidAddress);
+        }
+        let res = unsafe {
+            address.as_mut_vec().push(0);
+            tox_add_tcp_relay(self.raw, address.as_bytes().as_ptr() as *const _,
+                              port, public_key.raw.as_ptr())
+        };
+        match res {
+            1 => Ok(()),
+            _ => Err(BootstrapError::ResolveFailed),
+        }
+    }
+
+    pub fn bootstrap(&mut self, node: Box<BootstrapNode>) -> Result<(), BootstrapError> {
+        let key = Box::new(node.public_key.clone());
+        let mut res = self.bootstrap_from_address(node.address.clone(), node.udp_port,
+                                                  key.clone());
+        for &port in node.tcp_ports.iter() {
+            if self.add_tcp_relay(node.address.clone(), port, key.clone()).is_ok() {
+                res = Ok(());
+            }
         }
+        res
     }
 
-    fn is_connected(&mut self) -> bool {
+    pub fn is_connected(&mut self) -> bool {
         match unsafe { tox_isconnected(&*self.raw) } {
             0 => false,
             _ => true,
         }
     }
 
+    pub fn save(&mut self) -> Vec<u8> {
+        let size = unsafe { tox_size(&*self.raw) as usize };
+        let mut vec = Vec::with_capacity(size);
+        unsafe {
+            tox_save(&*self.raw, vec.as_mut_ptr());
+            vec.set_len(size);
+        }
+        vec
+    }
+
+    /// Load the data and overwrite it since it contains the secret key
+    pub fn load(&mut self, mut data: Vec<u8>) -> Result<(), LoadError> {
+        if data.len() == 0 {
+            return Err(LoadError::Empty);
+        }
+        if encryptsave::is_encrypted(&data) {
+            return Err(LoadError::Encrypted);
+        }
+        let res = unsafe { tox_load(self.raw, data.as_ptr(), data.len() as u32) };
+        util::zero_memory(&mut data);
+        match res {
+            0 => Ok(()),
+            _ => Err(LoadError::Invalid),
+        }
+    }
+}
+
+impl Backend {
     fn av(&mut self, max_calls: i32) -> Option<(AvControl, AvEvents)> {
         if self.av.is_some() {
             return None;
         }
         let (send_end, recv_end) = spsc::one_space::new();
-        let av = AvControl::new(self.raw, max_calls, send_end);
+        let (kill_send, kill_recv) = spsc::one_space::new();
+        let av = AvControl::new(self.core.raw, max_calls, send_end, kill_recv);
         if av.is_some() {
-            self.av = Some(recv_end);
+            self.av = Some(AvSession { end: recv_end, kill: kill_send });
         }
         av
     }
 
-    pub fn new(opts: &mut Tox_Options) -> Option<(ControlProducer, CoreEvents)> {
-        let tox = unsafe { tox_new(opts) };
-        if tox.is_null() {
+    pub fn new(opts: &mut ToxOptions)
+            -> Option<(ControlProducer, CoreEvents, AliveConsumer, JoinHandle)> {
+        if opts.event_capacity == 0 {
             return None;
         }
-        let (event_send, event_recv) = spsc::bounded::new(64);
-        let mut internal = Box::new(Internal { stop: false, events: event_send });
-
-        unsafe {
-            let ip = &mut *internal as *mut _ as *mut c_void;
-            tox_callback_friend_request(        tox, Some(on_friend_request),        ip);
-            tox_callback_friend_message(        tox, Some(on_friend_message),        ip);
-            tox_callback_friend_action(         tox, Some(on_friend_action),         ip);
-            tox_callback_name_change(           tox, Some(on_name_change),           ip);
-            tox_callback_status_message(        tox, Some(on_status_message),        ip);
-            tox_callback_user_status(           tox, Some(on_user_status),           ip);
-            tox_callback_typing_change(         tox, Some(on_typing_change),         ip);
-            tox_callback_read_receipt(          tox, Some(on_read_receipt),          ip);
-            tox_callback_connection_status(     tox, Some(on_connection_status),     ip);
-            tox_callback_group_invite(          tox, Some(on_group_invite),          ip);
-            tox_callback_group_message(         tox, Some(on_group_message),         ip);
-            tox_callback_group_action(          tox, Some(on_group_action),          ip);
-            tox_callback_group_namelist_change( tox, Some(on_group_namelist_change), ip);
-            tox_callback_file_send_request(     tox, Some(on_file_send_request),     ip);
-            tox_callback_file_control(          tox, Some(on_file_control),          ip);
-            tox_callback_file_data(             tox, Some(on_file_data),             ip);
-            tox_callback_avatar_info(           tox, Some(on_avatar_info),           ip);
-            tox_callback_avatar_data(           tox, Some(on_avatar_data),           ip);
-        }
-        let (control_send, control_recv) = spsc::one_space::new();
+        // The policies can only touch events that haven't been sent yet so half of
+        // the capacity is kept back for them
+        let buffered = match opts.overflow {
+            OverflowPolicy::Block => 0,
+            _ => opts.event_capacity / 2,
+        };
+        let (event_send, event_recv) = spsc::bounded::new(opts.event_capacity - buffered);
+        let sink = EventSink::Channel {
+            events: event_send,
+            pending: VecDeque::new(),
+            capacity: buffered,
+            policy: opts.overflow,
+        };
+        let core = match Core::new(&mut opts.txo, sink) {
+            Some(core) => core,
+            None => return None,
+        };
+        // Unbounded so that any number of asynchronous calls can be in flight
+        let (control_send, control_recv) = spsc::unbounded::new();
+        let (alive_send, alive_recv) = spsc::one_space::new();
         let backend = Backend {
-            raw: tox,
-            internal: internal,
+            core: core,
             control: control_recv,
             av: None,
+            alive: Some(alive_send),
         };
-        std::thread::spawn(move || backend.run());
-        Some((control_send, event_recv))
+        let thread = std::thread::spawn(move || backend.run());
+        Some((control_send, event_recv, alive_recv, thread))
     }
 
     fn run(mut self) {
         'outer: loop {
-            unsafe { tox_do(self.raw); }
-            if self.internal.stop {
+            self.core.iterate();
+            if self.core.internal.stop {
                 break 'outer;
             }
 
             'inner: loop {
                 match self.control.recv_async() {
+                    Ok(Control::Shutdown(ret)) => {
+                        self.shutdown(ret);
+                        return;
+                    },
                     Ok(ctrl) => self.control(ctrl),
                     Err(comm::Error::Disconnected) => break 'outer,
                     _ => break 'inner,
                 }
             }
 
-            if self.av.as_ref().map(|x| x.recv_async()) == Some(Err(comm::Error::Disconnected)) {
+            let av_ended = self.av.as_ref().map(|x| x.end.recv_async());
+            if av_ended == Some(Err(comm::Error::Disconnected)) {
                 self.av.take();
             }
 
-            let interval = unsafe { tox_do_interval(self.raw) as i64 };
+            let interval = self.core.iteration_interval() as i64;
             timer::sleep(Duration::milliseconds(interval));
         }
 
-        // If we have an AV session then we have to continue
-        if let Some(ref av) = self.av {
-            loop {
-                if av.recv_async() == Err(comm::Error::Disconnected) {
-                    break;
-                }
-                let interval = unsafe { tox_do_interval(self.raw) as i64 };
+        self.alive.take();
+
+        // If we have an AV session then we have to continue. Other calls are dropped
+        // so that the caller sees a dead backend but we still honor shutdown
+        // requests.
+        while self.av.is_some() {
+            match self.control.recv_async() {
+                Ok(Control::Shutdown(ret)) => {
+                    self.shutdown(ret);
+                    return;
+                },
+                _ => { },
+            }
+            let av_ended = self.av.as_ref().unwrap().end.recv_async();
+            if av_ended == Err(comm::Error::Disconnected) {
+                break;
+            }
+            let interval = self.core.iteration_interval() as i64;
+            timer::sleep(Duration::milliseconds(interval));
+            self.core.iterate();
+        }
+    }
+
+    /// Tears down the AV session and sends the final save data. The caller has to
+    /// return from `run` afterwards so that the tox instance is killed.
+    fn shutdown(&mut self, ret: OneSpaceProducer<Vec<u8>>) {
+        if let Some(AvSession { end, kill }) = self.av.take() {
+            drop(kill);
+            while end.recv_async() != Err(comm::Error::Disconnected) {
+                let interval = self.core.iteration_interval() as i64;
                 timer::sleep(Duration::milliseconds(interval));
-                unsafe { tox_do(self.raw); }
+                self.core.iterate();
             }
         }
+        let _ = ret.send(self.core.save());
     }
 
     fn control(&mut self, ctrl: Control) {
         match ctrl {
-            Control::GetAddress(ret) =>
-                ret.send(self.get_address()).unwrap(),
-            Control::AddFriend(addr, msg, ret) =>
-                ret.send(self.add_friend(addr, msg)).unwrap(),
-            Control::AddFriendNorequest(id, ret) =>
-                ret.send(self.add_friend_norequest(id)).unwrap(),
-            Control::GetFriendNumber(id, ret) =>
-                ret.send(self.get_friend_number(id)).unwrap(),
-            Control::GetClientId(friend, ret) =>
-                ret.send(self.get_client_id(friend)).unwrap(),
-            Control::DelFriend(friend, ret) =>
-                ret.send(self.del_friend(friend)).unwrap(),
-            Control::GetFriendConnectionStatus(friend, ret) =>
-                ret.send(self.get_friend_connection_status(friend)).unwrap(),
-            Control::FriendExists(friend, ret) =>
-                ret.send(self.friend_exists(friend)).unwrap(),
-            Control::SendMessage(friend, msg, ret) =>
-                ret.send(self.send_message(friend, msg)).unwrap(),
-            Control::SendAction(friend, act, ret) =>
-                ret.send(self.send_action(friend, act)).unwrap(),
-            Control::SetName(name, ret) =>
-                ret.send(self.set_name(name)).unwrap(),
-            Control::GetSelfName(ret) =>
-                ret.send(self.get_self_name()).unwrap(),
-            Control::GetName(friend, ret) =>
-                ret.send(self.get_name(friend)).unwrap(),
-            Control::SetStatusMessage(msg, ret) =>
-                ret.send(self.set_status_message(msg)).unwrap(),
-            Control::SetUserStatus(status, ret) =>
-                ret.send(self.set_user_status(status)).unwrap(),
-            Control::GetStatusMessage(friend, ret) =>
-                ret.send(self.get_status_message(friend)).unwrap(),
-            Control::GetSelfStatusMessage(ret) =>
-                ret.send(self.get_self_status_message()).unwrap(),
-            Control::GetUserStatus(friend, ret) =>
-                ret.send(self.get_user_status(friend)).unwrap(),
-            Control::GetSelfUserStatus(ret) =>
-                ret.send(self.get_self_user_status()).unwrap(),
-            Control::GetLastOnline(friend, ret) =>
-                ret.send(self.get_last_online(friend)).unwrap(),
-            Control::SetUserIsTyping(friend, is, ret) =>
-                ret.send(self.set_user_is_typing(friend, is)).unwrap(),
-            Control::GetIsTyping(friend, ret) =>
-                ret.send(self.get_is_typing(friend)).unwrap(),
-            Control::CountFriendlist(ret) =>
-                ret.send(self.count_friendlist()).unwrap(),
-            Control::GetNumOnlineFriends(ret) =>
-                ret.send(self.get_num_online_friends()).unwrap(),
-            Control::GetFriendlist(ret) =>
-                ret.send(self.get_friendlist()).unwrap(),
-            Control::GetNospam(ret) =>
-                ret.send(self.get_nospam()).unwrap(),
+            Control::GetAddress(ret) => {
+                let _ = ret.send(self.core.get_address());
+            },
+            Control::AddFriend(addr, msg, ret) => {
+                let res = self.core.add_friend(addr, msg);
+                let _ = ret.send(res.map_err(FriendAddError::Tox));
+            },
+            Control::AddFriendNorequest(id, ret) => {
+                let _ = ret.send(self.core.add_friend_norequest(id));
+            },
+            Control::GetFriendNumber(id, ret) => {
+                let _ = ret.send(self.core.get_friend_number(id));
+            },
+            Control::GetClientId(friend, ret) => {
+                let _ = ret.send(self.core.get_client_id(friend));
+            },
+            Control::DelFriend(friend, ret) => {
+                let _ = ret.send(self.core.del_friend(friend));
+            },
+            Control::GetFriendConnectionStatus(friend, ret) => {
+                let _ = ret.send(self.core.get_friend_connection_status(friend));
+            },
+            Control::FriendExists(friend, ret) => {
+                let _ = ret.send(self.core.friend_exists(friend));
+            },
+            Control::SendMessage(friend, msg, ret) => {
+                let _ = ret.send(self.core.send_message(friend, msg));
+            },
+            Control::SendAction(friend, act, ret) => {
+                let _ = ret.send(self.core.send_action(friend, act));
+            },
+            Control::SetName(name, ret) => {
+                let _ = ret.send(self.core.set_name(name));
+            },
+            Control::GetSelfName(ret) => {
+                let _ = ret.send(self.core.get_self_name());
+            },
+            Control::GetName(friend, ret) => {
+                let _ = ret.send(self.core.get_name(friend));
+            },
+            Control::SetStatusMessage(msg, ret) => {
+                let _ = ret.send(self.core.set_status_message(msg));
+            },
+            Control::SetUserStatus(status, ret) => {
+                let _ = ret.send(self.core.set_user_status(status));
+            },
+            Control::GetStatusMessage(friend, ret) => {
+                let _ = ret.send(self.core.get_status_message(friend));
+            },
+            Control::GetSelfStatusMessage(ret) => {
+                let _ = ret.send(self.core.get_self_status_message());
+            },
+            Control::GetUserStatus(friend, ret) => {
+                let _ = ret.send(self.core.get_user_status(friend));
+            },
+            Control::GetSelfUserStatus(ret) => {
+                let _ = ret.send(self.core.get_self_user_status());
+            },
+            Control::GetLastOnline(friend, ret) => {
+                let _ = ret.send(self.core.get_last_online(friend));
+            },
+            Control::SetUserIsTyping(friend, is, ret) => {
+                let _ = ret.send(self.core.set_user_is_typing(friend, is));
+            },
+            Control::GetIsTyping(friend, ret) => {
+                let _ = ret.send(self.core.get_is_typing(friend));
+            },
+            Control::CountFriendlist(ret) => {
+                let _ = ret.send(self.core.count_friendlist());
+            },
+            Control::GetNumOnlineFriends(ret) => {
+                let _ = ret.send(self.core.get_num_online_friends());
+            },
+            Control::GetFriendlist(ret) => {
+                let _ = ret.send(self.core.get_friendlist());
+            },
+            Control::GetNospam(ret) => {
+                let _ = ret.send(self.core.get_nospam());
+            },
             Control::SetNospam(ns) =>
-                self.set_nospam(ns),
-            Control::AddGroupchat(ret) =>
-                ret.send(self.add_groupchat()).unwrap(),
-            Control::DelGroupchat(group, ret) =>
-                ret.send(self.del_groupchat(group)).unwrap(),
-            Control::GroupPeername(group, peer, ret) =>
-                ret.send(self.group_peername(group, peer)).unwrap(),
-            Control::InviteFriend(friend, group, ret) =>
-                ret.send(self.invite_friend(friend, group)).unwrap(),
-            Control::JoinGroupchat(friend, group, ret) =>
-                ret.send(self.join_groupchat(friend, group)).unwrap(),
-            Control::GroupMessageSend(group, msg, ret) =>
-                ret.send(self.group_message_send(group, msg)).unwrap(),
-            Control::GroupActionSend(group, action, ret) =>
-                ret.send(self.group_action_send(group, action)).unwrap(),
-            Control::GroupNumberPeers(group, ret) =>
-                ret.send(self.group_number_peers(group)).unwrap(),
-            Control::GroupGetNames(group, ret) =>
-                ret.send(self.group_get_names(group)).unwrap(),
-            Control::CountChatlist(ret) =>
-                ret.send(self.count_chatlist()).unwrap(),
-            Control::GetChatlist(ret) =>
-                ret.send(self.get_chatlist()).unwrap(),
-            Control::SetAvatar(format, data, ret) =>
-                ret.send(self.set_avatar(format, data)).unwrap(),
+                self.core.set_nospam(ns),
+            Control::GetKeys(ret) => {
+                let _ = ret.send(self.core.get_keys());
+            },
+            Control::AddGroupchat(ret) => {
+                let _ = ret.send(self.core.add_groupchat());
+            },
+            Control::DelGroupchat(group, ret) => {
+                let _ = ret.send(self.core.del_groupchat(group));
+            },
+            Control::GroupPeername(group, peer, ret) => {
+                let _ = ret.send(self.core.group_peername(group, peer));
+            },
+            Control::GroupPeerPublicKey(group, peer, ret) => {
+                let _ = ret.send(self.core.group_peer_public_key(group, peer));
+            },
+            Control::GroupPeerIsOurs(group, peer, ret) => {
+                let _ = ret.send(self.core.group_peer_is_ours(group, peer));
+            },
+            Control::InviteFriend(friend, group, ret) => {
+                let _ = ret.send(self.core.invite_friend(friend, group));
+            },
+            Control::JoinGroupchat(friend, group, ret) => {
+                let _ = ret.send(self.core.join_groupchat(friend, group));
+            },
+            Control::GroupMessageSend(group, msg, ret) => {
+                let _ = ret.send(self.core.group_message_send(group, msg));
+            },
+            Control::GroupActionSend(group, action, ret) => {
+                let _ = ret.send(self.core.group_action_send(group, action));
+            },
+            Control::GroupSetTitle(group, title, ret) => {
+                let _ = ret.send(self.core.group_set_title(group, title));
+            },
+            Control::GroupGetTitle(group, ret) => {
+                let _ = ret.send(self.core.group_get_title(group));
+            },
+            Control::GroupNumberPeers(group, ret) => {
+                let _ = ret.send(self.core.group_number_peers(group));
+            },
+            Control::GroupGetNames(group, ret) => {
+                let _ = ret.send(self.core.group_get_names(group));
+            },
+            Control::CountChatlist(ret) => {
+                let _ = ret.send(self.core.count_chatlist());
+            },
+            Control::GetChatlist(ret) => {
+                let _ = ret.send(self.core.get_chatlist());
+            },
+            Control::GroupGetType(group, ret) => {
+                let _ = ret.send(self.core.group_get_type(group));
+            },
+            Control::SetAvatar(format, data, ret) => {
+                let _ = ret.send(self.core.set_avatar(format, data));
+            },
             Control::UnsetAvatar =>
-                self.unset_avatar(),
-            Control::GetSelfAvatar(ret) =>
-                ret.send(self.get_self_avatar()).unwrap(),
-            Control::RequestAvatarInfo(friend, ret) =>
-                ret.send(self.request_avatar_info(friend)).unwrap(),
-            Control::RequestAvatarData(friend, ret) =>
-                ret.send(self.request_avatar_data(friend)).unwrap(),
-            Control::SendAvatarInfo(friend, ret) =>
-                ret.send(self.send_avatar_info(friend)).unwrap(),
-            Control::NewFileSender(friend, size, file, ret) =>
-                ret.send(self.new_file_sender(friend, size, file)).unwrap(),
-            Control::FileSendControl(friend, ty, num, msg, data, ret) =>
-                ret.send(self.file_send_control(friend, ty, num, msg, data)).unwrap(),
-            Control::FileSendData(friend, num, data, ret) =>
-                ret.send(self.file_send_data(friend, num, data)).unwrap(),
-            Control::FileDataSize(friend, ret) =>
-                ret.send(self.file_data_size(friend)).unwrap(),
-            Control::FileDataRemaining(friend, num, ty, ret) =>
-                ret.send(self.file_data_remaining(friend, num, ty)).unwrap(),
-            Control::BootstrapFromAddress(addr, port, id, ret) =>
-                ret.send(self.bootstrap_from_address(addr, port, id)).unwrap(),
-            Control::Isconnected(ret) =>
-                ret.send(self.is_connected()).unwrap(),
-            Control::Save(ret) =>
-                ret.send(self.save()).unwrap(),
-            Control::Load(data, ret) =>
-                ret.send(self.load(data)).unwrap(),
-            Control::Raw(ret) =>
-                ret.send(self.raw).unwrap(),
-            Control::Av(max_calls, ret) =>
-                ret.send(self.av(max_calls)).map_err(|e|e.1).unwrap(),
-        }
-    }
-
-    fn save(&mut self) -> Vec<u8> {
-        let size = unsafe { tox_size(&*self.raw) as usize };
-        let mut vec = Vec::with_capacity(size);
-        unsafe {
-            tox_save(&*self.raw, vec.as_mut_ptr());
-            vec.set_len(size);
+                self.core.unset_avatar(),
+            Control::GetSelfAvatar(ret) => {
+                let _ = ret.send(self.core.get_self_avatar());
+            },
+            Control::RequestAvatarInfo(friend, ret) => {
+                let _ = ret.send(self.core.request_avatar_info(friend));
+            },
+            Control::RequestAvatarData(friend, ret) => {
+                let _ = ret.send(self.core.request_avatar_data(friend));
+            },
+            Control::SendAvatarInfo(friend, ret) => {
+                let _ = ret.send(self.core.send_avatar_info(friend));
+            },
+            Control::NewFileSender(friend, size, file, ret) => {
+                let _ = ret.send(self.core.new_file_sender(friend, size, file));
+            },
+            Control::FileSendControl(friend, ty, num, msg, data, ret) => {
+                let _ = ret.send(self.core.file_send_control(friend, ty, num, msg, data));
+            },
+            Control::FileSendData(friend, num, data, ret) => {
+                let _ = ret.send(self.core.file_send_data(friend, num, data));
+            },
+            Control::FileDataSize(friend, ret) => {
+                let _ = ret.send(self.core.file_data_size(friend));
+            },
+            Control::FileDataRemaining(friend, num, ty, ret) => {
+                let _ = ret.send(self.core.file_data_remaining(friend, num, ty));
+            },
+            Control::RegisterLossyPackets(friend, ids, ret) => {
+                let _ = ret.send(self.core.register_packets(friend, ids, false));
+            },
+            Control::RegisterLosslessPackets(friend, ids, ret) => {
+                let _ = ret.send(self.core.register_packets(friend, ids, true));
+            },
+            Control::SendLossyPacket(friend, id, data, ret) => {
+                let _ = ret.send(self.core.send_packet(friend, id, data, false));
+            },
+            Control::SendLosslessPacket(friend, id, data, ret) => {
+                let _ = ret.send(self.core.send_packet(friend, id, data, true));
+            },
+            Control::BootstrapFromAddress(addr, port, id, ret) => {
+                let _ = ret.send(self.core.bootstrap_from_address(addr, port, id));
+            },
+            Control::AddTcpRelay(addr, port, id, ret) => {
+                let _ = ret.send(self.core.add_tcp_relay(addr, port, id));
+            },
+            Control::Bootstrap(node, ret) => {
+                let _ = ret.send(self.core.bootstrap(node));
+            },
+            Control::Isconnected(ret) => {
+                let _ = ret.send(self.core.is_connected());
+            },
+            Control::DroppedEvents(ret) => {
+                let _ = ret.send(self.core.dropped_events());
+            },
+            Control::LastConnect(ret) => {
+                let _ = ret.send(self.core.last_connect());
+            },
+            Control::LastDisconnect(ret) => {
+                let _ = ret.send(self.core.last_disconnect());
+            },
+            Control::Save(ret) => {
+                let _ = ret.send(self.core.save());
+            },
+            Control::Load(data, ret) => {
+                let _ = ret.send(self.core.load(data));
+            },
+            Control::Raw(ret) => {
+                let _ = ret.send(self.core.raw);
+            },
+            Control::Av(max_calls, ret) => {
+                let _ = ret.send(self.av(max_calls));
+            },
+            // Handled in `run`
+            Control::Shutdown(..) => unreachable!(),
         }
-        vec
     }
+}
 
-    fn load(&mut self, data: Vec<u8>) -> Result<(), ()> {
-        match unsafe { tox_load(self.raw, data.as_ptr(), data.len() as u32) } {
-            0 => Ok(()),
-            _ => Err(()),
-        }
-    }
+type EventProducer = spsc::bounded::Producer<'static, Event>;
+
+/// Where the callbacks put the events they produce
+pub enum EventSink {
+    /// Send the events to a `CoreEvents` receiver. Events that don't fit into the
+    /// channel are buffered in `pending`. `policy` applies once `pending` holds
+    /// `capacity` events. The channel and `capacity` together hold the event
+    /// capacity of the options
+    Channel {
+        events: EventProducer,
+        pending: VecDeque<Event>,
+        capacity: usize,
+        policy: OverflowPolicy,
+    },
+    /// Collect the events until they are taken by `Core::take_events`
+    Queue(Vec<Event>),
 }
 
 struct Internal {
     stop: bool,
-    events: spsc::bounded::Producer<'static, Event>,
+    /// Number of events that were dropped because of the overflow policy
+    dropped: u64,
+    events: EventSink,
+}
+
+impl Internal {
+    fn send(&mut self, event: Event) {
+        let res = match self.events {
+            EventSink::Channel { ref events, ref mut pending, capacity, policy } =>
+                enqueue(events, pending, capacity, policy, event),
+            EventSink::Queue(ref mut queue) => {
+                queue.push(event);
+                Ok(0)
+            },
+        };
+        match res {
+            Ok(n) => self.dropped += n,
+            Err(()) => self.stop = true,
+        }
+    }
+
+    /// Moves buffered events to the channel
+    fn flush(&mut self) {
+        let res = match self.events {
+            EventSink::Channel { ref events, ref mut pending, .. } =>
+                flush(events, pending),
+            EventSink::Queue(..) => Ok(()),
+        };
+        if res.is_err() {
+            self.stop = true;
+        }
+    }
+}
+
+/// Moves as many pending events to the channel as fit. Fails if the receiver has been
+/// dropped.
+fn flush(events: &EventProducer, pending: &mut VecDeque<Event>) -> Result<(), ()> {
+    while let Some(event) = pending.pop_front() {
+        match events.send_async(event) {
+            Ok(()) => { },
+            Err((comm::Error::Full, event)) => {
+                pending.push_front(event);
+                break;
+            },
+            Err(_) => return Err(()),
+        }
+    }
+    Ok(())
+}
+
+/// Sends or buffers the event according to the policy. Returns the number of events
+/// that were dropped or fails if the receiver has been dropped.
+fn enqueue(events: &EventProducer, pending: &mut VecDeque<Event>, capacity: usize,
+           policy: OverflowPolicy, mut event: Event) -> Result<u64, ()> {
+    if policy == OverflowPolicy::Block {
+        return events.send_sync(event).map(|_| 0).map_err(|_| ());
+    }
+    try!(flush(events, pending));
+    if pending.is_empty() {
+        match events.send_async(event) {
+            Ok(()) => return Ok(0),
+            Err((comm::Error::Full, ev)) => event = ev,
+            Err(_) => return Err(()),
+        }
+    }
+    if pending.len() < capacity {
+        pending.push_back(event);
+        return Ok(0);
+    }
+    match policy {
+        OverflowPolicy::DropOldest => {
+            // Without a buffer the new event is the oldest one we still have
+            if pending.pop_front().is_some() {
+                pending.push_back(event);
+            }
+            Ok(1)
+        },
+        OverflowPolicy::DropNewest => Ok(1),
+        _ => {
+            match pending.iter().position(|old| supersedes(&event, old)) {
+                Some(pos) => {
+                    pending.remove(pos);
+                    pending.push_back(event);
+                    Ok(0)
+                },
+                // Nothing to coalesce. Blocking would stop the tox loop
+                None => Ok(1),
+            }
+        },
+    }
+}
+
+/// Returns `true` if `new` makes `old` obsolete
+fn supersedes(new: &Event, old: &Event) -> bool {
+    match (new, old) {
+        (&NameChange(a, _),          &NameChange(b, _))          => a == b,
+        (&StatusMessage(a, _),       &StatusMessage(b, _))       => a == b,
+        (&UserStatusVar(a, _),       &UserStatusVar(b, _))       => a == b,
+        (&TypingChange(a, _),        &TypingChange(b, _))        => a == b,
+        (&ConnectionStatusVar(a, _), &ConnectionStatusVar(b, _)) => a == b,
+        (&GroupTitle(a, _, _),       &GroupTitle(b, _, _))       => a == b,
+        (&SelfConnectionStatus(_),   &SelfConnectionStatus(_))   => true,
+        _ => false,
+    }
+}
+
+/// Userdata of a custom packet handler. toxcore doesn't pass the friend number to
+/// these callbacks so we have to store it ourselves.
+struct PacketHandler {
+    internal: *mut Internal,
+    friendnumber: i32,
+    lossless: bool,
 }
 
 macro_rules! get_int {
@@ -878,22 +1482,13 @@ macro_rules! get_int {
 
 macro_rules! send_or_stop {
     ($internal:ident, $event:expr) => {
-        match $internal.events.send_sync($event) {
-            Ok(()) => { },
-            _ => $internal.stop = true,
-        }
+        $internal.send($event)
     }
 }
 
 macro_rules! parse_string {
     ($p:ident, $l:ident) => {
-        {
-            let slice = to_slice($p as *const u8, $l as usize);
-            match std::str::from_utf8(slice) {
-                Ok(s) => s.to_string(),
-                _ => return,
-            }
-        }
+        ToxString::from_bytes(to_slice($p as *const u8, $l as usize).to_vec())
     }
 }
 
@@ -996,7 +1591,7 @@ extern fn on_group_action(_: *mut Tox, groupnumber: i32, frindgroupnumber: i32,
                            action: *const u8, len: u16, internal: *mut c_void) {
     let internal = get_int!(internal);
     let action = parse_string!(action, len);
-    send_or_stop!(internal, GroupMessage(groupnumber, frindgroupnumber, action));
+    send_or_stop!(internal, GroupAction(groupnumber, frindgroupnumber, action));
 }
 
 extern fn on_group_namelist_change(_: *mut Tox, groupnumber: i32, peernumber: i32,
@@ -1011,6 +1606,13 @@ extern fn on_group_namelist_change(_: *mut Tox, groupnumber: i32, peernumber: i3
     send_or_stop!(internal, GroupNamelistChange(groupnumber, peernumber, change));
 }
 
+extern fn on_group_title(_: *mut Tox, groupnumber: i32, peernumber: i32,
+                          title: *const u8, len: u8, internal: *mut c_void) {
+    let internal = get_int!(internal);
+    let title = parse_string!(title, len);
+    send_or_stop!(internal, GroupTitle(groupnumber, peernumber, title));
+}
+
 extern fn on_file_send_request(_: *mut Tox, friendnumber: i32, filenumber: u8,
                                filesize: u64, filename: *const u8, len: u16,
                                internal: *mut c_void) {
@@ -1075,3 +1677,19 @@ extern fn on_avatar_data(_: *mut Tox, friendnumber: i32, format: u8, hash: *mut
     let data = unsafe { slice::from_raw_parts_mut(data, datalen as usize).to_vec() };
     send_or_stop!(internal, AvatarData(friendnumber, format, hash, data));
 }
+
+extern fn on_custom_packet(handler: *mut c_void, data: *const u8, len: u32) -> c_int {
+    let handler = unsafe { &*(handler as *const PacketHandler) };
+    let internal = unsafe { &mut *handler.internal };
+    if internal.stop || len == 0 {
+        return 0;
+    }
+    let data = to_slice(data, len as usize);
+    let (id, data) = (data[0], data[1..].to_vec());
+    let event = match handler.lossless {
+        true => LosslessPacket(handler.friendnumber, id, data),
+        false => LossyPacket(handler.friendnumber, id, data),
+    };
+    send_or_stop!(internal, event);
+    0
+}
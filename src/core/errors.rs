@@ -0,0 +1,346 @@
+//! Error types returned by the core functions.
+
+use std::{error, fmt};
+
+use core::ll;
+
+macro_rules! display_description {
+    ($($ty:ident),+) => {
+        $(
+            impl fmt::Display for $ty {
+                fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                    fmt.write_str(error::Error::description(self))
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! from_disconnected {
+    ($($ty:ident),+) => {
+        $(
+            impl From<Disconnected> for $ty {
+                fn from(_: Disconnected) -> $ty {
+                    $ty::Disconnected
+                }
+            }
+        )+
+    }
+}
+
+display_description!(Faerr, FriendAddError, FriendError, MessageError, GroupError,
+                     FileError, AvatarError, PacketError, BootstrapError, LoadError,
+                     SaveError, ParseError, Disconnected);
+
+from_disconnected!(FriendAddError, FriendError, MessageError, GroupError, FileError,
+                   AvatarError, PacketError, BootstrapError, LoadError, SaveError);
+
+/// The backend thread has stopped, e.g., because the event receiver was dropped
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Disconnected;
+
+impl error::Error for Disconnected {
+    fn description(&self) -> &str {
+        "backend thread has stopped"
+    }
+}
+
+/// Faerr - Friend Add Error
+#[repr(i32)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Faerr {
+    Toolong      = ll::TOX_FAERR_TOOLONG,
+    Nomessage    = ll::TOX_FAERR_NOMESSAGE,
+    Ownkey       = ll::TOX_FAERR_OWNKEY,
+    Alreadysent  = ll::TOX_FAERR_ALREADYSENT,
+    Unknown      = ll::TOX_FAERR_UNKNOWN,
+    Badchecksum  = ll::TOX_FAERR_BADCHECKSUM,
+    Setnewnospam = ll::TOX_FAERR_SETNEWNOSPAM,
+    Nomem        = ll::TOX_FAERR_NOMEM,
+}
+
+impl error::Error for Faerr {
+    fn description(&self) -> &str {
+        match *self {
+            Faerr::Toolong      => "friend request message is too long",
+            Faerr::Nomessage    => "friend request message is empty",
+            Faerr::Ownkey       => "address is our own address",
+            Faerr::Alreadysent  => "friend request has already been sent",
+            Faerr::Unknown      => "unknown error",
+            Faerr::Badchecksum  => "address has a bad checksum",
+            Faerr::Setnewnospam => "friend is already added but the nospam differs",
+            Faerr::Nomem        => "out of memory",
+        }
+    }
+}
+
+/// Errors of `ToxControl::add_friend`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FriendAddError {
+    /// toxcore refused to add the friend
+    Tox(Faerr),
+    /// The backend thread has stopped
+    Disconnected,
+}
+
+impl error::Error for FriendAddError {
+    fn description(&self) -> &str {
+        match *self {
+            FriendAddError::Tox(ref e)   => e.description(),
+            FriendAddError::Disconnected => "backend thread has stopped",
+        }
+    }
+}
+
+/// Errors of the functions that operate on friends
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FriendError {
+    /// There is no friend with the given friend number or client id
+    NotFound,
+    /// The friend could not be added, e.g., because it's already in the friend list
+    AddFailed,
+    /// The backend thread has stopped
+    Disconnected,
+}
+
+impl error::Error for FriendError {
+    fn description(&self) -> &str {
+        match *self {
+            FriendError::NotFound     => "friend not found",
+            FriendError::AddFailed    => "friend could not be added",
+            FriendError::Disconnected => "backend thread has stopped",
+        }
+    }
+}
+
+/// Errors of the functions that send messages or set and get our own name and status
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MessageError {
+    /// The message, name or status message is too long
+    TooLong,
+    /// The name is empty
+    Empty,
+    /// The message could not be sent, e.g., because the friend does not exist
+    NotSent,
+    /// toxcore reported an unspecified error
+    Failed,
+    /// The backend thread has stopped
+    Disconnected,
+}
+
+impl error::Error for MessageError {
+    fn description(&self) -> &str {
+        match *self {
+            MessageError::TooLong      => "message too long",
+            MessageError::Empty        => "message is empty",
+            MessageError::NotSent      => "message could not be sent",
+            MessageError::Failed       => "unspecified error",
+            MessageError::Disconnected => "backend thread has stopped",
+        }
+    }
+}
+
+/// Errors of the groupchat functions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GroupError {
+    /// There is no groupchat with the given group number
+    NotFound,
+    /// There is no peer with the given peer number in the groupchat
+    PeerNotFound,
+    /// The message or title is too long
+    TooLong,
+    /// The friend could not be invited
+    InviteFailed,
+    /// The groupchat could not be joined
+    JoinFailed,
+    /// The message could not be sent
+    SendFailed,
+    /// toxcore reported an unspecified error
+    Failed,
+    /// The backend thread has stopped
+    Disconnected,
+}
+
+impl error::Error for GroupError {
+    fn description(&self) -> &str {
+        match *self {
+            GroupError::NotFound     => "groupchat not found",
+            GroupError::PeerNotFound => "peer not found",
+            GroupError::TooLong      => "message too long",
+            GroupError::InviteFailed => "friend could not be invited",
+            GroupError::JoinFailed   => "groupchat could not be joined",
+            GroupError::SendFailed   => "message could not be sent",
+            GroupError::Failed       => "unspecified error",
+            GroupError::Disconnected => "backend thread has stopped",
+        }
+    }
+}
+
+/// Errors of the file transfer functions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FileError {
+    /// There is no friend with the given friend number
+    NotFound,
+    /// The filename or the data is too long
+    TooLong,
+    /// toxcore reported an unspecified error, e.g., because the transfer does not
+    /// exist or the send queue is full
+    Failed,
+    /// The backend thread has stopped
+    Disconnected,
+}
+
+impl error::Error for FileError {
+    fn description(&self) -> &str {
+        match *self {
+            FileError::NotFound     => "friend not found",
+            FileError::TooLong      => "data too long",
+            FileError::Failed       => "unspecified error",
+            FileError::Disconnected => "backend thread has stopped",
+        }
+    }
+}
+
+/// Errors of the avatar functions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AvatarError {
+    /// The avatar data is larger than `AVATAR_MAX_DATA_LENGTH`
+    TooLong,
+    /// toxcore returned an unknown avatar format
+    InvalidFormat,
+    /// The request could not be sent, e.g., because the friend is offline
+    NotSent,
+    /// toxcore reported an unspecified error
+    Failed,
+    /// The backend thread has stopped
+    Disconnected,
+}
+
+impl error::Error for AvatarError {
+    fn description(&self) -> &str {
+        match *self {
+            AvatarError::TooLong       => "avatar too large",
+            AvatarError::InvalidFormat => "invalid avatar format",
+            AvatarError::NotSent       => "avatar request could not be sent",
+            AvatarError::Failed        => "unspecified error",
+            AvatarError::Disconnected  => "backend thread has stopped",
+        }
+    }
+}
+
+/// Errors returned by the custom packet functions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PacketError {
+    /// The packet id is outside of the range reserved for the packet type
+    InvalidId,
+    /// The packet is larger than `MAX_CUSTOM_PACKET_SIZE`
+    TooLong,
+    /// The friend does not exist
+    NoFriend,
+    /// The packet could not be sent, e.g., because the friend is offline
+    SendFailed,
+    /// The backend thread has stopped
+    Disconnected,
+}
+
+impl error::Error for PacketError {
+    fn description(&self) -> &str {
+        match *self {
+            PacketError::InvalidId    => "packet id outside of the allowed range",
+            PacketError::TooLong      => "packet too long",
+            PacketError::NoFriend     => "friend not found",
+            PacketError::SendFailed   => "packet could not be sent",
+            PacketError::Disconnected => "backend thread has stopped",
+        }
+    }
+}
+
+/// Errors of the bootstrap functions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BootstrapError {
+    /// The address contains a nul byte
+    InvalidAddress,
+    /// The address could not be resolved
+    ResolveFailed,
+    /// The backend thread has stopped
+    Disconnected,
+}
+
+impl error::Error for BootstrapError {
+    fn description(&self) -> &str {
+        match *self {
+            BootstrapError::InvalidAddress => "address contains a nul byte",
+            BootstrapError::ResolveFailed  => "address could not be resolved",
+            BootstrapError::Disconnected   => "backend thread has stopped",
+        }
+    }
+}
+
+/// Errors of `load`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LoadError {
+    /// The data is empty
+    Empty,
+    /// toxcore could not parse the data
+    Invalid,
+    /// The data is encrypted. Use `load_encrypted` instead
+    Encrypted,
+    /// The passphrase is wrong or the encrypted data has been corrupted
+    Decryption,
+    /// The backend thread has stopped
+    Disconnected,
+}
+
+impl error::Error for LoadError {
+    fn description(&self) -> &str {
+        match *self {
+            LoadError::Empty        => "save data is empty",
+            LoadError::Invalid      => "save data is invalid",
+            LoadError::Encrypted    => "save data is encrypted",
+            LoadError::Decryption   => "save data could not be decrypted",
+            LoadError::Disconnected => "backend thread has stopped",
+        }
+    }
+}
+
+/// Errors of `save_encrypted`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SaveError {
+    /// The save data could not be encrypted
+    Encryption,
+    /// The backend thread has stopped
+    Disconnected,
+}
+
+impl error::Error for SaveError {
+    fn description(&self) -> &str {
+        match *self {
+            SaveError::Encryption   => "save data could not be encrypted",
+            SaveError::Disconnected => "backend thread has stopped",
+        }
+    }
+}
+
+/// Errors of parsing `Address`es and `ClientId`s
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ParseError {
+    /// The string has the wrong length
+    WrongLength,
+    /// The string contains a character that is not a hex digit
+    BadHex,
+    /// The checksum of the address does not match
+    BadChecksum,
+    /// The string is not a valid `tox:` URI
+    BadUri,
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        match *self {
+            ParseError::WrongLength => "wrong length",
+            ParseError::BadHex      => "invalid hex digit",
+            ParseError::BadChecksum => "bad checksum",
+            ParseError::BadUri      => "invalid tox URI",
+        }
+    }
+}
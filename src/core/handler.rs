@@ -0,0 +1,94 @@
+//! Callback style access to the tox events.
+//!
+//! # Example (a simple echo bot)
+//!
+//! ```no_run
+//! use tox::core::*;
+//!
+//! struct Echo {
+//!     tox: ToxControl,
+//! }
+//!
+//! impl ToxHandler for Echo {
+//!     fn on_friend_message(&mut self, fnum: i32, msg: ToxString) {
+//!         let _ = self.tox.send_message(fnum, msg.to_string_lossy());
+//!     }
+//! }
+//!
+//! let (tox, events) = ToxControl::new(ToxOptions::new()).unwrap();
+//! Echo { tox: tox }.run(&events);
+//! ```
+
+use core::{Event, CoreEvents, ClientId, UserStatus, ConnectionStatus, GroupchatType,
+           ChatChange, TransferType, ControlType, AvatarFormat, Hash, ToxString};
+use core::Event::*;
+
+/// Callbacks for the events of a tox instance. Every method corresponds to the
+/// variant of `Event` with the same name and does nothing by default.
+#[allow(unused_variables)]
+pub trait ToxHandler {
+    fn on_friend_request(&mut self, id: Box<ClientId>, msg: ToxString) { }
+    fn on_friend_message(&mut self, fnum: i32, msg: ToxString) { }
+    fn on_friend_action(&mut self, fnum: i32, action: ToxString) { }
+    fn on_name_change(&mut self, fnum: i32, name: ToxString) { }
+    fn on_status_message(&mut self, fnum: i32, status: ToxString) { }
+    fn on_user_status(&mut self, fnum: i32, status: UserStatus) { }
+    fn on_typing_change(&mut self, fnum: i32, is_typing: bool) { }
+    fn on_read_receipt(&mut self, fnum: i32, receipt: u32) { }
+    fn on_connection_status(&mut self, fnum: i32, status: ConnectionStatus) { }
+    fn on_group_invite(&mut self, fnum: i32, ty: GroupchatType, data: Vec<u8>) { }
+    fn on_group_message(&mut self, gnum: i32, pnum: i32, msg: ToxString) { }
+    fn on_group_action(&mut self, gnum: i32, pnum: i32, action: ToxString) { }
+    fn on_group_namelist_change(&mut self, gnum: i32, pnum: i32, change: ChatChange) { }
+    fn on_group_title(&mut self, gnum: i32, pnum: i32, title: ToxString) { }
+    fn on_file_send_request(&mut self, fnum: i32, fid: u8, size: u64, name: Vec<u8>) { }
+    fn on_file_control(&mut self, fnum: i32, ty: TransferType, fid: u8,
+                       control: ControlType, data: Vec<u8>) { }
+    fn on_file_data(&mut self, fnum: i32, fid: u8, data: Vec<u8>) { }
+    fn on_avatar_info(&mut self, fnum: i32, format: AvatarFormat, hash: Hash) { }
+    fn on_avatar_data(&mut self, fnum: i32, format: AvatarFormat, hash: Hash,
+                      data: Vec<u8>) { }
+    fn on_lossy_packet(&mut self, fnum: i32, id: u8, data: Vec<u8>) { }
+    fn on_lossless_packet(&mut self, fnum: i32, id: u8, data: Vec<u8>) { }
+    fn on_self_connection_status(&mut self, status: ConnectionStatus) { }
+
+    /// Call the method that corresponds to the event
+    fn handle(&mut self, event: Event) {
+        match event {
+            FriendRequest(id, msg)              => self.on_friend_request(id, msg),
+            FriendMessage(fnum, msg)            => self.on_friend_message(fnum, msg),
+            FriendAction(fnum, act)             => self.on_friend_action(fnum, act),
+            NameChange(fnum, name)              => self.on_name_change(fnum, name),
+            StatusMessage(fnum, status)         => self.on_status_message(fnum, status),
+            UserStatusVar(fnum, status)         => self.on_user_status(fnum, status),
+            TypingChange(fnum, is)              => self.on_typing_change(fnum, is),
+            ReadReceipt(fnum, receipt)          => self.on_read_receipt(fnum, receipt),
+            ConnectionStatusVar(fnum, status)   => self.on_connection_status(fnum, status),
+            GroupInvite(fnum, ty, data)         => self.on_group_invite(fnum, ty, data),
+            GroupMessage(gnum, pnum, msg)       => self.on_group_message(gnum, pnum, msg),
+            GroupAction(gnum, pnum, act)        => self.on_group_action(gnum, pnum, act),
+            GroupNamelistChange(gnum, pnum, ch) =>
+                self.on_group_namelist_change(gnum, pnum, ch),
+            GroupTitle(gnum, pnum, title)       => self.on_group_title(gnum, pnum, title),
+            FileSendRequest(fnum, fid, size, name) =>
+                self.on_file_send_request(fnum, fid, size, name),
+            FileControl(fnum, ty, fid, control, data) =>
+                self.on_file_control(fnum, ty, fid, control, data),
+            FileData(fnum, fid, data)           => self.on_file_data(fnum, fid, data),
+            AvatarInfo(fnum, format, hash)      => self.on_avatar_info(fnum, format, hash),
+            AvatarData(fnum, format, hash, data) =>
+                self.on_avatar_data(fnum, format, hash, data),
+            LossyPacket(fnum, id, data)         => self.on_lossy_packet(fnum, id, data),
+            LosslessPacket(fnum, id, data)      => self.on_lossless_packet(fnum, id, data),
+            SelfConnectionStatus(status)        => self.on_self_connection_status(status),
+        }
+    }
+
+    /// Pass all events received on `events` to `handle`. Returns when the backend
+    /// thread has stopped
+    fn run(&mut self, events: &CoreEvents) {
+        while let Ok(event) = events.recv_sync() {
+            self.handle(event);
+        }
+    }
+}
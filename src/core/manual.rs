@@ -0,0 +1,516 @@
+//! A tox instance that is driven by the caller instead of a background thread.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::old_io::{timer};
+//! use std::time::{Duration};
+//! use tox::core::*;
+//!
+//! let mut tox = Tox::new(ToxOptions::new()).unwrap();
+//! loop {
+//!     for ev in tox.iterate() {
+//!         if let FriendMessage(fnum, msg) = ev {
+//!             tox.send_message(fnum, msg).unwrap();
+//!         }
+//!     }
+//!     timer::sleep(Duration::milliseconds(tox.iteration_interval() as i64));
+//! }
+//! ```
+
+use std::path::{PathBuf};
+use std::ops::{Range};
+
+use core::{ll, Address, ClientId, PublicKey, SecretKey, ConnectionStatus, UserStatus,
+           GroupchatType, AvatarFormat, Hash, TransferType, BootstrapNode, Event,
+           ToxOptions, Faerr, FriendError, MessageError, GroupError, FileError,
+           AvatarError, PacketError, BootstrapError, LoadError, SaveError, ToxString};
+use encryptsave::{self, DecryptError};
+use util;
+use super::backend::{Core, EventSink};
+
+/// A tox instance without a background thread. `iterate` has to be called every
+/// `iteration_interval` milliseconds and returns the events that happened since the
+/// last call. The other methods behave like the ones of `ToxControl` but since there
+/// is no thread, none of them can fail with `Disconnected`.
+pub struct Tox {
+    core: Core,
+}
+
+impl Tox {
+    /// Create a new tox instance
+    #[inline]
+    pub fn new(mut opts: ToxOptions) -> Option<Tox> {
+        match Core::new(&mut opts.txo, EventSink::Queue(Vec::new())) {
+            Some(core) => Some(Tox { core: core }),
+            None => None,
+        }
+    }
+
+    /// Returns the number of milliseconds after which `iterate` should be called
+    /// again
+    #[inline]
+    pub fn iteration_interval(&self) -> u32 {
+        self.core.iteration_interval()
+    }
+
+    /// Run one iteration of the tox loop and return the events it produced
+    #[inline]
+    pub fn iterate(&mut self) -> Vec<Event> {
+        self.core.iterate();
+        self.core.take_events()
+    }
+
+    /// See `ToxControl::get_address`
+    #[inline]
+    pub fn get_address(&mut self) -> Address {
+        self.core.get_address()
+    }
+
+    /// See `ToxControl::add_friend`
+    #[inline]
+    pub fn add_friend(&mut self, address: Box<Address>,
+                      msg: String) -> Result<i32, Faerr> {
+        self.core.add_friend(address, msg)
+    }
+
+    /// See `ToxControl::add_friend_norequest`
+    #[inline]
+    pub fn add_friend_norequest(&mut self,
+                                client_id: Box<ClientId>) -> Result<i32, FriendError> {
+        self.core.add_friend_norequest(client_id)
+    }
+
+    /// See `ToxControl::get_friend_number`
+    #[inline]
+    pub fn get_friend_number(&mut self,
+                             client_id: Box<ClientId>) -> Result<i32, FriendError> {
+        self.core.get_friend_number(client_id)
+    }
+
+    /// See `ToxControl::get_client_id`
+    #[inline]
+    pub fn get_client_id(&mut self,
+                         friendnumber: i32) -> Result<Box<ClientId>, FriendError> {
+        self.core.get_client_id(friendnumber)
+    }
+
+    /// See `ToxControl::del_friend`
+    #[inline]
+    pub fn del_friend(&mut self, friendnumber: i32) -> Result<(), FriendError> {
+        self.core.del_friend(friendnumber)
+    }
+
+    /// See `ToxControl::get_friend_connection_status`
+    #[inline]
+    pub fn get_friend_connection_status(
+            &mut self,
+            friendnumber: i32) -> Result<ConnectionStatus, FriendError> {
+        self.core.get_friend_connection_status(friendnumber)
+    }
+
+    /// See `ToxControl::friend_exists`
+    #[inline]
+    pub fn friend_exists(&mut self, friendnumber: i32) -> bool {
+        self.core.friend_exists(friendnumber)
+    }
+
+    /// See `ToxControl::send_message`
+    #[inline]
+    pub fn send_message(&mut self, friendnumber: i32,
+                        msg: String) -> Result<u32, MessageError> {
+        self.core.send_message(friendnumber, msg)
+    }
+
+    /// See `ToxControl::send_action`
+    #[inline]
+    pub fn send_action(&mut self, friendnumber: i32,
+                       action: String) -> Result<u32, MessageError> {
+        self.core.send_action(friendnumber, action)
+    }
+
+    /// See `ToxControl::set_name`
+    #[inline]
+    pub fn set_name(&mut self, name: String) -> Result<(), MessageError> {
+        self.core.set_name(name)
+    }
+
+    /// See `ToxControl::get_self_name`
+    #[inline]
+    pub fn get_self_name(&mut self) -> Result<ToxString, MessageError> {
+        self.core.get_self_name()
+    }
+
+    /// See `ToxControl::get_name`
+    #[inline]
+    pub fn get_name(&mut self, friendnumber: i32) -> Result<ToxString, FriendError> {
+        self.core.get_name(friendnumber)
+    }
+
+    /// See `ToxControl::set_status_message`
+    #[inline]
+    pub fn set_status_message(&mut self, status: String) -> Result<(), MessageError> {
+        self.core.set_status_message(status)
+    }
+
+    /// See `ToxControl::set_user_status`
+    #[inline]
+    pub fn set_user_status(&mut self,
+                           userstatus: UserStatus) -> Result<(), MessageError> {
+        self.core.set_user_status(userstatus)
+    }
+
+    /// See `ToxControl::get_status_message`
+    #[inline]
+    pub fn get_status_message(&mut self,
+                              friendnumber: i32) -> Result<ToxString, FriendError> {
+        self.core.get_status_message(friendnumber)
+    }
+
+    /// See `ToxControl::get_self_status_message`
+    #[inline]
+    pub fn get_self_status_message(&mut self) -> Result<ToxString, MessageError> {
+        self.core.get_self_status_message()
+    }
+
+    /// See `ToxControl::get_user_status`
+    #[inline]
+    pub fn get_user_status(&mut self,
+                           friendnumber: i32) -> Result<UserStatus, FriendError> {
+        self.core.get_user_status(friendnumber)
+    }
+
+    /// See `ToxControl::get_self_user_status`
+    #[inline]
+    pub fn get_self_user_status(&mut self) -> Result<UserStatus, MessageError> {
+        self.core.get_self_user_status()
+    }
+
+    /// See `ToxControl::get_last_online`
+    #[inline]
+    pub fn get_last_online(&mut self, friendnumber: i32) -> Result<u64, FriendError> {
+        self.core.get_last_online(friendnumber)
+    }
+
+    /// See `ToxControl::set_user_is_typing`
+    #[inline]
+    pub fn set_user_is_typing(&mut self, friendnumber: i32,
+                              is_typing: bool) -> Result<(), FriendError> {
+        self.core.set_user_is_typing(friendnumber, is_typing)
+    }
+
+    /// See `ToxControl::get_is_typing`
+    #[inline]
+    pub fn get_is_typing(&mut self, friendnumber: i32) -> bool {
+        self.core.get_is_typing(friendnumber)
+    }
+
+    /// See `ToxControl::count_friendlist`
+    #[inline]
+    pub fn count_friendlist(&mut self) -> u32 {
+        self.core.count_friendlist()
+    }
+
+    /// See `ToxControl::count_chatlist`
+    #[inline]
+    pub fn count_chatlist(&mut self) -> u32 {
+        self.core.count_chatlist()
+    }
+
+    /// See `ToxControl::get_num_online_friends`
+    #[inline]
+    pub fn get_num_online_friends(&mut self) -> u32 {
+        self.core.get_num_online_friends()
+    }
+
+    /// See `ToxControl::get_friendlist`
+    #[inline]
+    pub fn get_friendlist(&mut self) -> Vec<i32> {
+        self.core.get_friendlist()
+    }
+
+    /// See `ToxControl::get_nospam`
+    #[inline]
+    pub fn get_nospam(&mut self) -> [u8; 4] {
+        self.core.get_nospam()
+    }
+
+    /// See `ToxControl::set_nospam`
+    #[inline]
+    pub fn set_nospam(&mut self, nospam: [u8; 4]) {
+        self.core.set_nospam(nospam)
+    }
+
+    /// See `ToxControl::get_keys`
+    #[inline]
+    pub fn get_keys(&mut self) -> (Box<PublicKey>, Box<SecretKey>) {
+        self.core.get_keys()
+    }
+
+    /// See `ToxControl::add_groupchat`
+    #[inline]
+    pub fn add_groupchat(&mut self) -> Result<i32, GroupError> {
+        self.core.add_groupchat()
+    }
+
+    /// See `ToxControl::del_groupchat`
+    #[inline]
+    pub fn del_groupchat(&mut self, groupnumber: i32) -> Result<(), GroupError> {
+        self.core.del_groupchat(groupnumber)
+    }
+
+    /// See `ToxControl::group_peername`
+    #[inline]
+    pub fn group_peername(&mut self, groupnumber: i32,
+                          peernumber: i32) -> Result<ToxString, GroupError> {
+        self.core.group_peername(groupnumber, peernumber)
+    }
+
+    /// See `ToxControl::group_peer_public_key`
+    #[inline]
+    pub fn group_peer_public_key(&mut self, groupnumber: i32,
+                                 peernumber: i32) -> Result<Box<ClientId>, GroupError> {
+        self.core.group_peer_public_key(groupnumber, peernumber)
+    }
+
+    /// See `ToxControl::group_peer_is_ours`
+    #[inline]
+    pub fn group_peer_is_ours(&mut self, groupnumber: i32,
+                              peernumber: i32) -> bool {
+        self.core.group_peer_is_ours(groupnumber, peernumber)
+    }
+
+    /// See `ToxControl::invite_friend`
+    #[inline]
+    pub fn invite_friend(&mut self, friendnumber: i32,
+                         groupnumber: i32) -> Result<(), GroupError> {
+        self.core.invite_friend(friendnumber, groupnumber)
+    }
+
+    /// See `ToxControl::join_groupchat`
+    #[inline]
+    pub fn join_groupchat(&mut self, friendnumber: i32,
+                          data: Vec<u8>) -> Result<i32, GroupError> {
+        self.core.join_groupchat(friendnumber, data)
+    }
+
+    /// See `ToxControl::group_message_send`
+    #[inline]
+    pub fn group_message_send(&mut self, groupnumber: i32,
+                              message: String) -> Result<(), GroupError> {
+        self.core.group_message_send(groupnumber, message)
+    }
+
+    /// See `ToxControl::group_action_send`
+    #[inline]
+    pub fn group_action_send(&mut self, groupnumber: i32,
+                             action: String) -> Result<(), GroupError> {
+        self.core.group_action_send(groupnumber, action)
+    }
+
+    /// See `ToxControl::group_set_title`
+    #[inline]
+    pub fn group_set_title(&mut self, groupnumber: i32,
+                           title: String) -> Result<(), GroupError> {
+        self.core.group_set_title(groupnumber, title)
+    }
+
+    /// See `ToxControl::group_get_title`
+    #[inline]
+    pub fn group_get_title(&mut self,
+                           groupnumber: i32) -> Result<ToxString, GroupError> {
+        self.core.group_get_title(groupnumber)
+    }
+
+    /// See `ToxControl::group_number_peers`
+    #[inline]
+    pub fn group_number_peers(&mut self, groupnumber: i32) -> Result<i32, GroupError> {
+        self.core.group_number_peers(groupnumber)
+    }
+
+    /// See `ToxControl::group_get_names`
+    #[inline]
+    pub fn group_get_names(&mut self,
+                           groupnumber: i32) -> Result<Vec<ToxString>, GroupError> {
+        self.core.group_get_names(groupnumber)
+    }
+
+    /// See `ToxControl::group_get_type`
+    #[inline]
+    pub fn group_get_type(&mut self,
+                          groupnumber: i32) -> Result<GroupchatType, GroupError> {
+        self.core.group_get_type(groupnumber)
+    }
+
+    /// See `ToxControl::get_chatlist`
+    #[inline]
+    pub fn get_chatlist(&mut self) -> Vec<(i32, GroupchatType)> {
+        self.core.get_chatlist()
+    }
+
+    #[inline]
+    pub fn set_avatar(&mut self, format: AvatarFormat,
+                      data: Vec<u8>) -> Result<(), AvatarError> {
+        self.core.set_avatar(format, data)
+    }
+
+    #[inline]
+    pub fn unset_avatar(&mut self) {
+        self.core.unset_avatar()
+    }
+
+    #[inline]
+    pub fn get_self_avatar(&mut self)
+                               -> Result<(AvatarFormat, Vec<u8>, Hash), AvatarError> {
+        self.core.get_self_avatar()
+    }
+
+    #[inline]
+    pub fn request_avatar_info(&mut self, friendnumber: i32) -> Result<(), AvatarError> {
+        self.core.request_avatar_info(friendnumber)
+    }
+
+    #[inline]
+    pub fn send_avatar_info(&mut self, friendnumber: i32) -> Result<(), AvatarError> {
+        self.core.send_avatar_info(friendnumber)
+    }
+
+    #[inline]
+    pub fn request_avatar_data(&mut self, friendnumber: i32) -> Result<(), AvatarError> {
+        self.core.request_avatar_data(friendnumber)
+    }
+
+    #[inline]
+    pub fn new_file_sender(&mut self, friendnumber: i32, filesize: u64,
+                           filename: PathBuf) -> Result<i32, FileError> {
+        self.core.new_file_sender(friendnumber, filesize, filename)
+    }
+
+    #[inline]
+    pub fn file_send_control(&mut self, friendnumber: i32, send_receive: TransferType,
+                             filenumber: u8, message_id: u8,
+                             data: Vec<u8>) -> Result<(), FileError> {
+        self.core.file_send_control(friendnumber, send_receive, filenumber, message_id,
+                                    data)
+    }
+
+    #[inline]
+    pub fn file_send_data(&mut self, friendnumber: i32, filenumber: u8,
+                          data: Vec<u8>) -> Result<(), FileError> {
+        self.core.file_send_data(friendnumber, filenumber, data)
+    }
+
+    #[inline]
+    pub fn file_data_size(&mut self, friendnumber: i32) -> Result<i32, FileError> {
+        self.core.file_data_size(friendnumber)
+    }
+
+    #[inline]
+    pub fn file_data_remaining(&mut self, friendnumber: i32, filenumber: u8,
+                               send_receive: TransferType) -> Result<u64, FileError> {
+        self.core.file_data_remaining(friendnumber, filenumber, send_receive)
+    }
+
+    /// See `ToxControl::register_lossy_packets`
+    #[inline]
+    pub fn register_lossy_packets(&mut self, friendnumber: i32,
+                                  ids: Range<u8>) -> Result<(), PacketError> {
+        self.core.register_packets(friendnumber, ids, false)
+    }
+
+    /// See `ToxControl::register_lossless_packets`
+    #[inline]
+    pub fn register_lossless_packets(&mut self, friendnumber: i32,
+                                     ids: Range<u8>) -> Result<(), PacketError> {
+        self.core.register_packets(friendnumber, ids, true)
+    }
+
+    /// See `ToxControl::send_lossy_packet`
+    #[inline]
+    pub fn send_lossy_packet(&mut self, friendnumber: i32, id: u8,
+                             data: Vec<u8>) -> Result<(), PacketError> {
+        self.core.send_packet(friendnumber, id, data, false)
+    }
+
+    /// See `ToxControl::send_lossless_packet`
+    #[inline]
+    pub fn send_lossless_packet(&mut self, friendnumber: i32, id: u8,
+                                data: Vec<u8>) -> Result<(), PacketError> {
+        self.core.send_packet(friendnumber, id, data, true)
+    }
+
+    /// See `ToxControl::bootstrap_from_address`
+    #[inline]
+    pub fn bootstrap_from_address(&mut self, address: String, port: u16,
+                                  public_key: Box<ClientId>) -> Result<(), BootstrapError> {
+        self.core.bootstrap_from_address(address, port, public_key)
+    }
+
+    /// See `ToxControl::add_tcp_relay`
+    #[inline]
+    pub fn add_tcp_relay(&mut self, address: String, port: u16,
+                         public_key: Box<ClientId>) -> Result<(), BootstrapError> {
+        self.core.add_tcp_relay(address, port, public_key)
+    }
+
+    /// See `ToxControl::bootstrap`
+    #[inline]
+    pub fn bootstrap(&mut self, node: Box<BootstrapNode>) -> Result<(), BootstrapError> {
+        self.core.bootstrap(node)
+    }
+
+    /// See `ToxControl::is_connected`
+    #[inline]
+    pub fn is_connected(&mut self) -> bool {
+        self.core.is_connected()
+    }
+
+    /// See `ToxControl::last_connect`
+    #[inline]
+    pub fn last_connect(&self) -> Option<u64> {
+        self.core.last_connect()
+    }
+
+    /// See `ToxControl::last_disconnect`
+    #[inline]
+    pub fn last_disconnect(&self) -> Option<u64> {
+        self.core.last_disconnect()
+    }
+
+    /// See `ToxControl::save`
+    #[inline]
+    pub fn save(&mut self) -> Vec<u8> {
+        self.core.save()
+    }
+
+    /// See `ToxControl::load`
+    #[inline]
+    pub fn load(&mut self, data: Vec<u8>) -> Result<(), LoadError> {
+        self.core.load(data)
+    }
+
+    /// Returns a tox data encrypted with `passphrase`
+    pub fn save_encrypted(&mut self, passphrase: &str) -> Result<Vec<u8>, SaveError> {
+        let mut data = self.core.save();
+        let res = encryptsave::encrypt(&data, passphrase)
+                      .map_err(|_| SaveError::Encryption);
+        util::zero_memory(&mut data);
+        res
+    }
+
+    /// Load instance data that may be encrypted with `passphrase`
+    pub fn load_encrypted(&mut self, data: Vec<u8>,
+                          passphrase: &str) -> Result<(), LoadError> {
+        let data = match encryptsave::decrypt(&data, passphrase) {
+            Ok(plain) => plain,
+            Err(DecryptError::NotEncrypted) => data,
+            Err(DecryptError::Failed) => return Err(LoadError::Decryption),
+        };
+        self.core.load(data)
+    }
+
+    #[inline]
+    pub unsafe fn raw(&self) -> *mut ll::Tox {
+        self.core.raw()
+    }
+}
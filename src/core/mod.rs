@@ -3,43 +3,62 @@
 //! # Example (a simple echo bot)
 //!
 //! ```no_run
-//! #![feature(globs)]
 //! extern crate tox;
-//! 
+//!
 //! use tox::core::*;
-//! 
+//!
 //! fn main() {
-//!     let tox = Tox::new(ToxOptions::new()).unwrap();
-//!     let bootkey = box "951C88B7E75C867418ACDB5D273821372BB5BD652740BCDF623A4FA293E75D2F".parse().unwrap();
+//!     let (tox, events) = ToxControl::new(ToxOptions::new()).unwrap();
+//!     let bootkey = Box::new("951C88B7E75C867418ACDB5D273821372BB5BD652740BCDF623A4FA293E75D2F"
+//!                            .parse().unwrap());
 //!     tox.bootstrap_from_address("192.254.75.98".to_string(), 33445, bootkey).unwrap();
-//! 
-//!     println!("Bot key: {}", tox.get_address());
-//! 
-//!     for ev in tox.events().iter() {
+//!
+//!     println!("Bot key: {}", tox.get_address().unwrap());
+//!
+//!     while let Ok(ev) = events.recv_sync() {
 //!         match ev {
-//!             FriendRequest(fnum, _) => {
-//!                 tox.add_friend_norequest(fnum).unwrap();
+//!             Event::FriendRequest(id, _) => {
+//!                 tox.add_friend_norequest(id).unwrap();
 //!             },
-//!             FriendMessage(fnum, msg) => {
-//!                 tox.send_message(fnum, msg).unwrap();
+//!             Event::FriendMessage(fnum, msg) => {
+//!                 tox.send_message(fnum, msg.to_string_lossy()).unwrap();
 //!             },
 //!             _ => (),
 //!         }
 //!     }
 //! }
 //! ```
+//!
+//! `Tox` offers the same methods without a background thread. Its events are
+//! returned by `Tox::iterate`.
 
 // TODO: Wrap unwrapped core functions
 
-use std::{fmt, mem};
+use std::{self, fmt, mem};
 use std::str::{FromStr};
 use std::path::{PathBuf};
+use std::ops::{Range};
+use std::thread::{JoinHandle};
 use std::slice::{IntSliceExt};
-use comm::{spsc};
+use comm::{self, spsc};
 pub use self::Event::*;
 use av::{AvControl, AvEvents};
+use future::{Future, Reply};
+use encryptsave::{self, DecryptError};
+use util;
+
+pub use self::errors::{Faerr, FriendAddError, FriendError, MessageError, GroupError,
+                       FileError, AvatarError, PacketError, BootstrapError, LoadError,
+                       SaveError, ParseError, Disconnected};
+pub use self::manual::{Tox};
+pub use self::handler::{ToxHandler};
+pub use self::uri::{ToxUri};
 
 mod backend;
+mod errors;
+mod manual;
+mod handler;
+mod uri;
 pub mod ll;
 
 pub const MAX_NAME_LENGTH:              usize = 128usize;
@@ -50,8 +69,19 @@ pub const ID_CLIENT_SIZE:               usize = 32usize;
 pub const ADDRESS_SIZE:                 usize = ID_CLIENT_SIZE + 6usize;
 pub const AVATAR_MAX_DATA_LENGTH:       usize = 16384usize;
 pub const HASH_LENGTH:                  usize = 32usize;
-
-type ControlProducer = spsc::one_space::Producer<'static, backend::Control>;
+pub const SECRET_KEY_SIZE:              usize = 32usize;
+pub const MAX_CUSTOM_PACKET_SIZE:       usize = 1373usize;
+pub const MAX_FILENAME_LENGTH:          usize = 255usize;
+
+/// First and last packet id (inclusive) that can be used for lossy custom packets
+pub const LOSSY_PACKET_ID_FIRST:    u8 = 200u8;
+pub const LOSSY_PACKET_ID_LAST:     u8 = 254u8;
+/// First and last packet id (inclusive) that can be used for lossless custom packets
+pub const LOSSLESS_PACKET_ID_FIRST: u8 = 160u8;
+pub const LOSSLESS_PACKET_ID_LAST:  u8 = 191u8;
+
+type ControlProducer = spsc::unbounded::Producer<'static, backend::Control>;
+type AliveConsumer = spsc::one_space::Consumer<'static, ()>;
 pub type CoreEvents = spsc::bounded::Consumer<'static, Event>;
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -72,16 +102,16 @@ pub enum GroupchatType {
 #[derive(Clone, Debug)]
 pub enum Event {
     /// The first value is the client id, the second is the friend request message
-    FriendRequest(Box<ClientId>, String),
+    FriendRequest(Box<ClientId>, ToxString),
     /// `(fnum, msg)` where `fnum` is the friend number and `msg` is the received message
-    FriendMessage(i32, String),
+    FriendMessage(i32, ToxString),
     /// `(fnum, msg)` where `fnum` is the friend number and `msg` is the action message
-    FriendAction(i32, String),
+    FriendAction(i32, ToxString),
     /// `(fnum, name)` where `fnum` is the friend number and `name` is the new friend name
-    NameChange(i32, String),
+    NameChange(i32, ToxString),
     /// `(fnum, status)` where `fnum` is the friend number and `status` is the status
     /// message
-    StatusMessage(i32, String),
+    StatusMessage(i32, ToxString),
     /// `(fnum, usrst)` where `fnum` is the friend number and `usrst` is the friend status
     UserStatusVar(i32, UserStatus),
     /// `(fnum, is_typing)`. `true` value of is_typing means that friend is typing. `fnum`
@@ -97,9 +127,15 @@ pub enum Event {
     GroupInvite(i32, GroupchatType, Vec<u8>),
     /// `(gnum, pnum, msg)` where `gnum` is the group number, `pnum` is the peer number
     /// and `msg` is the message
-    GroupMessage(i32, i32, String),
+    GroupMessage(i32, i32, ToxString),
+    /// `(gnum, pnum, action)` where `gnum` is the group number, `pnum` is the peer
+    /// number and `action` is the action message
+    GroupAction(i32, i32, ToxString),
     /// `(gnum, pnum, ChatChange)`
     GroupNamelistChange(i32, i32, ChatChange),
+    /// `(gnum, pnum, title)` where `pnum` is the peer number of the peer who changed
+    /// the title and `title` is the new title
+    GroupTitle(i32, i32, ToxString),
     /// `(fnum, fid, fisize, finame)`
     FileSendRequest(i32, u8, u64, Vec<u8>),
     /// `(fnum, TranserType, fid, ControlType, data)`
@@ -110,6 +146,14 @@ pub enum Event {
     AvatarInfo(i32, AvatarFormat, Hash),
     /// `(fnum, AvatarFormat, Hash, data)`
     AvatarData(i32, AvatarFormat, Hash, Vec<u8>),
+    /// `(fnum, id, data)` where `id` is the packet id and `data` is the payload
+    /// without the id byte
+    LossyPacket(i32, u8, Vec<u8>),
+    /// `(fnum, id, data)` where `id` is the packet id and `data` is the payload
+    /// without the id byte
+    LosslessPacket(i32, u8, Vec<u8>),
+    /// Our connection to the DHT has been established or lost
+    SelfConnectionStatus(ConnectionStatus),
 }
 
 /// A Tox address consist of `ClientId`, nospam and checksum
@@ -123,10 +167,34 @@ pub struct Address {
 }
 
 impl Address {
+    /// Create the address of the id with the nospam
+    pub fn new(id: &ClientId, nospam: [u8; 4]) -> Address {
+        let mut adr = Address { id: id.clone(), nospam: nospam, checksum: [0; 2] };
+        adr.checksum = adr.checksum();
+        adr
+    }
+
+    /// Parse an address as it is entered by users. Whitespace is ignored and the
+    /// address can have a `tox:` prefix. Unlike `from_str`, invalid characters are
+    /// reported before a wrong length
+    pub fn parse_tolerant(s: &str) -> Result<Address, ParseError> {
+        let s = uri::strip_scheme(s.trim());
+        let hex: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        if !hex.chars().all(|c| c.is_digit(16)) {
+            return Err(ParseError::BadHex);
+        }
+        hex.parse()
+    }
+
     #[inline]
     pub fn client_id(&self) -> &ClientId {
         &self.id
     }
+
+    #[inline]
+    pub fn nospam(&self) -> [u8; 4] {
+        self.nospam
+    }
     fn checksum(&self) -> [u8; 2] {
         let mut check = [0u8, 0u8];
         for (i, &x) in self.id.raw.iter().enumerate() {
@@ -154,40 +222,36 @@ impl fmt::Display for Address {
 }
 
 impl FromStr for Address {
-    type Err = ();
+    type Err = ParseError;
 
-    fn from_str(s: &str) -> Result<Address, ()> {
+    fn from_str(s: &str) -> Result<Address, ParseError> {
         if s.len() != 2 * ADDRESS_SIZE {
-            return Err(());
+            return Err(ParseError::WrongLength);
+        }
+        // Slicing below would panic in the middle of a multi-byte character
+        if s.bytes().any(|b| b >= 0x80) {
+            return Err(ParseError::BadHex);
         }
 
         let mut id     = [0u8; 32];
         let mut nospam = [0u8; 4];
         let mut check  = [0u8; 2];
 
-        if parse_hex(&s[0..2*ID_CLIENT_SIZE], id.as_mut_slice()).is_err() {
-            return Err(());
-        }
-        if parse_hex(&s[2*ID_CLIENT_SIZE..2*ID_CLIENT_SIZE+8],
-                             nospam.as_mut_slice()).is_err() {
-            return Err(());
-        }
-        if parse_hex(&s[2*ID_CLIENT_SIZE+8..2*ADDRESS_SIZE],
-                             check.as_mut_slice()).is_err() {
-            return Err(());
-        }
+        try!(parse_hex(&s[0..2*ID_CLIENT_SIZE], id.as_mut_slice()));
+        try!(parse_hex(&s[2*ID_CLIENT_SIZE..2*ID_CLIENT_SIZE+8], nospam.as_mut_slice()));
+        try!(parse_hex(&s[2*ID_CLIENT_SIZE+8..2*ADDRESS_SIZE], check.as_mut_slice()));
 
         let addr = Address { id: ClientId { raw: id }, nospam: nospam, checksum: check };
         if addr.checksum().as_slice() != check.as_slice() {
-            return Err(());
+            return Err(ParseError::BadChecksum);
         }
         Ok(addr)
     }
 }
 
-fn parse_hex(s: &str, buf: &mut [u8]) -> Result<(),()> {
+fn parse_hex(s: &str, buf: &mut [u8]) -> Result<(), ParseError> {
     if s.len() != 2*buf.len() {
-        return Err(());
+        return Err(ParseError::WrongLength);
     }
     for i in range(0usize, buf.len()) {
         for j in range(0usize, 2) {
@@ -195,7 +259,7 @@ fn parse_hex(s: &str, buf: &mut [u8]) -> Result<(),()> {
                 c @ '0' ... '9' => (c as u8) - ('0' as u8),
                 c @ 'a' ... 'f' => (c as u8) - ('a' as u8) + 10,
                 c @ 'A' ... 'F' => (c as u8) - ('A' as u8) + 10,
-                _              => return Err(()),
+                _              => return Err(ParseError::BadHex),
             }
         }
     }
@@ -220,22 +284,67 @@ impl fmt::Display for ClientId {
 }
 
 impl FromStr for ClientId {
-    type Err = ();
+    type Err = ParseError;
 
-    fn from_str(s: &str) -> Result<ClientId, ()> {
+    fn from_str(s: &str) -> Result<ClientId, ParseError> {
         if s.len() != 2 * ID_CLIENT_SIZE {
-            return Err(());
+            return Err(ParseError::WrongLength);
         }
 
         let mut id = [0u8; ID_CLIENT_SIZE];
-
-        if parse_hex(s, id.as_mut_slice()).is_err() {
-            return Err(());
-        }
+        try!(parse_hex(s, id.as_mut_slice()));
         Ok(ClientId { raw: id })
     }
 }
 
+/// The public part of the long-term keypair
+pub type PublicKey = ClientId;
+
+/// The secret part of the long-term keypair
+///
+/// The key is overwritten with zeros when it's dropped and its `Debug`
+/// implementation doesn't print the key.
+pub struct SecretKey {
+    raw: [u8; SECRET_KEY_SIZE],
+}
+
+impl SecretKey {
+    #[inline]
+    pub fn new(raw: [u8; SECRET_KEY_SIZE]) -> SecretKey {
+        SecretKey { raw: raw }
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        util::zero_memory(&mut self.raw);
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "SecretKey(..)")
+    }
+}
+
+/// A node that can be used for UDP bootstrapping and as a TCP relay
+#[derive(Clone, Debug)]
+pub struct BootstrapNode {
+    /// IP address or hostname of the node
+    pub address: String,
+    /// Port used for UDP bootstrapping
+    pub udp_port: u16,
+    /// Ports on which the node accepts TCP relay connections
+    pub tcp_ports: Vec<u16>,
+    /// Public key of the node
+    pub public_key: ClientId,
+}
+
 /// Locally-calculated cryptographic hash of the avatar data
 #[derive(Clone, PartialEq, Eq, Debug)]
 #[allow(missing_copy_implementations)]
@@ -245,18 +354,79 @@ pub struct Hash {
 
 impl Hash {
     #[inline]
-    pub fn new(data: &[u8]) -> Result<Hash, ()> {
+    pub fn new(data: &[u8]) -> Result<Hash, AvatarError> {
         let mut hash: Hash = unsafe { mem::uninitialized() };
         let res = unsafe {
             ll::tox_hash(hash.hash.as_mut_ptr(), data.as_ptr(), data.len() as u32)
         };
         match res {
             0 => Ok(hash),
-            _ => Err(()),
+            _ => Err(AvatarError::Failed),
         }
     }
 }
 
+/// Text received from another client. Clients are not required to send valid UTF-8 so
+/// the raw bytes are kept and decoding is left to the user
+#[derive(Clone, PartialEq, Eq)]
+pub struct ToxString {
+    raw: Vec<u8>,
+}
+
+impl ToxString {
+    #[inline]
+    pub fn from_bytes(raw: Vec<u8>) -> ToxString {
+        ToxString { raw: raw }
+    }
+
+    /// Returns the raw bytes
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    #[inline]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.raw
+    }
+
+    /// Returns the text if it is valid UTF-8
+    #[inline]
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.raw).ok()
+    }
+
+    /// Returns the text if it is valid UTF-8. Otherwise, returns `self` unchanged
+    #[inline]
+    pub fn into_string(self) -> Result<String, ToxString> {
+        String::from_utf8(self.raw).map_err(|e| ToxString { raw: e.into_bytes() })
+    }
+
+    /// Returns the text with invalid UTF-8 sequences replaced by U+FFFD
+    #[inline]
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.raw).into_owned()
+    }
+}
+
+impl From<String> for ToxString {
+    fn from(s: String) -> ToxString {
+        ToxString { raw: s.into_bytes() }
+    }
+}
+
+impl fmt::Display for ToxString {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&String::from_utf8_lossy(&self.raw), fmt)
+    }
+}
+
+impl fmt::Debug for ToxString {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&String::from_utf8_lossy(&self.raw), fmt)
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum ConnectionStatus {
     Online,
@@ -289,20 +459,6 @@ pub enum ControlType {
     ResumeBroken = ll::TOX_FILECONTROL_RESUME_BROKEN,
 }
 
-/// Faerr - Friend Add Error
-#[repr(i32)]
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
-pub enum Faerr {
-    Toolong      = ll::TOX_FAERR_TOOLONG,
-    Nomessage    = ll::TOX_FAERR_NOMESSAGE,
-    Ownkey       = ll::TOX_FAERR_OWNKEY,
-    Alreadysent  = ll::TOX_FAERR_ALREADYSENT,
-    Unknown      = ll::TOX_FAERR_UNKNOWN,
-    Badchecksum  = ll::TOX_FAERR_BADCHECKSUM,
-    Setnewnospam = ll::TOX_FAERR_SETNEWNOSPAM,
-    Nomem        = ll::TOX_FAERR_NOMEM,
-}
-
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum TransferType {
     Receiving,
@@ -319,7 +475,9 @@ pub enum TransferType {
 /// ```
 #[derive(Copy, Clone, Debug)]
 pub struct ToxOptions {
-    txo: ll::Tox_Options
+    txo: ll::Tox_Options,
+    event_capacity: usize,
+    overflow: OverflowPolicy,
 }
 
 #[repr(u8)]
@@ -330,6 +488,21 @@ pub enum ProxyType {
     HTTP,
 }
 
+/// What the backend does with new events if the event receiver does not keep up
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OverflowPolicy {
+    /// Wait until the receiver has made room. This stops the tox loop
+    Block,
+    /// Drop the oldest buffered event
+    DropOldest,
+    /// Drop the new event
+    DropNewest,
+    /// Replace a buffered status event (name, status message, user status, typing,
+    /// connection status, group title) with a newer one of the same kind for the same
+    /// friend or group. Other events are handled like `DropNewest`
+    Coalesce,
+}
+
 impl ToxOptions {
     /// Create a default ToxOptions struct
     #[inline]
@@ -341,7 +514,9 @@ impl ToxOptions {
                 proxy_type: 0,
                 proxy_address: [0; 256usize],
                 proxy_port: 0,
-            }
+            },
+            event_capacity: 64,
+            overflow: OverflowPolicy::Block,
         }
     }
 
@@ -373,67 +548,154 @@ impl ToxOptions {
         self.txo.proxy_port = port;
         self
     }
+
+    /// Set the number of events that can be queued before `overflow_policy` applies.
+    /// The default is 64. `ToxControl::new` fails if the capacity is zero
+    #[inline]
+    pub fn event_capacity(mut self, capacity: usize) -> ToxOptions {
+        self.event_capacity = capacity;
+        self
+    }
+
+    /// Set what happens to new events if the event queue is full. The default is
+    /// `OverflowPolicy::Block`
+    #[inline]
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> ToxOptions {
+        self.overflow = policy;
+        self
+    }
 }
 
 pub struct ToxControl {
     control: ControlProducer,
+    thread: JoinHandle,
+    /// Disconnected once the backend stops answering calls
+    alive: AliveConsumer,
 }
 
+// `->` forwards a call whose reply is a `Result` and merges a dead backend into its
+// error type. `=>` forwards a call whose reply is a plain value. Both return a
+// `Reply` that resolves once the backend has answered. Calls without a reply only
+// report whether the backend received them. The control channel is unbounded so
+// that queueing a call never blocks.
 macro_rules! forward {
     ($slf:expr, $name:expr, ($($pp:ident),+), ->) => {{
-            let (snd, rcv) = spsc::one_space::new();
-            $slf.control.send($name($($pp),*, snd)).map_err(|e|e.1).unwrap();
-            rcv.recv_sync().unwrap()
+        let (snd, rcv) = spsc::one_space::new();
+        let _ = $slf.control.send($name($($pp),*, snd));
+        Reply::new(rcv, flatten)
     }};
     ($slf:expr, $name:expr, ->) => {{
-            let (snd, rcv) = spsc::one_space::new();
-            $slf.control.send($name(snd)).map_err(|e|e.1).unwrap();
-            rcv.recv_sync().unwrap()
+        let (snd, rcv) = spsc::one_space::new();
+        let _ = $slf.control.send($name(snd));
+        Reply::new(rcv, flatten)
+    }};
+    ($slf:expr, $name:expr, ($($pp:ident),+), =>) => {{
+        let (snd, rcv) = spsc::one_space::new();
+        let _ = $slf.control.send($name($($pp),*, snd));
+        Reply::new(rcv, identity)
+    }};
+    ($slf:expr, $name:expr, =>) => {{
+        let (snd, rcv) = spsc::one_space::new();
+        let _ = $slf.control.send($name(snd));
+        Reply::new(rcv, identity)
     }};
     ($slf:expr, $name:expr, ($($pp:ident),+)) => {{
-            $slf.control.send($name($($pp),*)).map_err(|e|e.1).unwrap()
+        $slf.control.send($name($($pp),*)).map_err(|_| Disconnected)
     }};
     ($slf:expr, $name:expr) => {
-            $slf.control.send($name).map_err(|e|e.1).unwrap()
+        $slf.control.send($name).map_err(|_| Disconnected)
     };
+}
+
+fn flatten<T, E: From<Disconnected>>(res: Result<Result<T, E>, Disconnected>)
+                                     -> Result<T, E> {
+    match res {
+        Ok(res) => res,
+        Err(e) => Err(From::from(e)),
+    }
+}
 
+fn identity<T>(res: T) -> T {
+    res
 }
 
 impl ToxControl {
     /// Get self address
     #[inline]
-    pub fn get_address(&self) -> Address {
-        forward!(self, backend::Control::GetAddress, ->)
+    pub fn get_address(&self) -> Result<Address, Disconnected> {
+        self.get_address_async().wait()
+    }
+
+    /// Asynchronous version of `get_address`
+    #[inline]
+    pub fn get_address_async(&self) -> Reply<Result<Address, Disconnected>> {
+        forward!(self, backend::Control::GetAddress,=>)
     }
 
     /// Add a friend and send friend request
     #[inline]
-    pub fn add_friend(&self, address: Box<Address>, msg: String) -> Result<i32, Faerr> {
+    pub fn add_friend(&self, address: Box<Address>,
+                      msg: String) -> Result<i32, FriendAddError> {
+        self.add_friend_async(address, msg).wait()
+    }
+
+    /// Asynchronous version of `add_friend`
+    #[inline]
+    pub fn add_friend_async(&self, address: Box<Address>, msg: String)
+            -> Reply<Result<i32, FriendAddError>> {
         forward!(self, backend::Control::AddFriend, (address, msg), ->)
     }
 
     /// Add a friend without sending friend request. Beware, friend will appear online
     /// only if he added you too
     #[inline]
-    pub fn add_friend_norequest(&self, client_id: Box<ClientId>) -> Result<i32, ()> {
+    pub fn add_friend_norequest(&self,
+                                client_id: Box<ClientId>) -> Result<i32, FriendError> {
+        self.add_friend_norequest_async(client_id).wait()
+    }
+
+    /// Asynchronous version of `add_friend_norequest`
+    #[inline]
+    pub fn add_friend_norequest_async(&self, client_id: Box<ClientId>)
+            -> Reply<Result<i32, FriendError>> {
         forward!(self, backend::Control::AddFriendNorequest, (client_id), ->)
     }
 
     /// Get friend number associated with given ClientId
     #[inline]
-    pub fn get_friend_number(&self, client_id: Box<ClientId>) -> Result<i32, ()> {
+    pub fn get_friend_number(&self, client_id: Box<ClientId>) -> Result<i32, FriendError> {
+        self.get_friend_number_async(client_id).wait()
+    }
+
+    /// Asynchronous version of `get_friend_number`
+    #[inline]
+    pub fn get_friend_number_async(&self, client_id: Box<ClientId>)
+            -> Reply<Result<i32, FriendError>> {
         forward!(self, backend::Control::GetFriendNumber, (client_id), ->)
     }
 
     /// Get ClientId of the friend with given friend number
     #[inline]
-    pub fn get_client_id(&self, friendnumber: i32) -> Result<Box<ClientId>, ()> {
+    pub fn get_client_id(&self, friendnumber: i32) -> Result<Box<ClientId>, FriendError> {
+        self.get_client_id_async(friendnumber).wait()
+    }
+
+    /// Asynchronous version of `get_client_id`
+    #[inline]
+    pub fn get_client_id_async(&self, friendnumber: i32)
+            -> Reply<Result<Box<ClientId>, FriendError>> {
         forward!(self, backend::Control::GetClientId, (friendnumber), ->)
     }
 
     /// Remove the friend with given friend number
     #[inline]
-    pub fn del_friend(&self, friendnumber: i32) -> Result<(),()> {
+    pub fn del_friend(&self, friendnumber: i32) -> Result<(), FriendError> {
+        self.del_friend_async(friendnumber).wait()
+    }
+
+    /// Asynchronous version of `del_friend`
+    #[inline]
+    pub fn del_friend_async(&self, friendnumber: i32) -> Reply<Result<(), FriendError>> {
         forward!(self, backend::Control::DelFriend, (friendnumber), ->)
     }
 
@@ -441,87 +703,185 @@ impl ToxControl {
     #[inline]
     pub fn get_friend_connection_status(
             &self,
-            friendnumber: i32) -> Result<ConnectionStatus, ()> {
+            friendnumber: i32) -> Result<ConnectionStatus, FriendError> {
+        self.get_friend_connection_status_async(friendnumber).wait()
+    }
+
+    /// Asynchronous version of `get_friend_connection_status`
+    #[inline]
+    pub fn get_friend_connection_status_async(
+            &self,
+            friendnumber: i32) -> Reply<Result<ConnectionStatus, FriendError>> {
         forward!(self, backend::Control::GetFriendConnectionStatus, (friendnumber), ->)
     }
 
     /// Returns `true` if friend with given friend number exists. Otherwise, returns
     /// `false`
     #[inline]
-    pub fn friend_exists(&self, friendnumber: i32) -> bool {
-        forward!(self, backend::Control::FriendExists, (friendnumber), ->)
+    pub fn friend_exists(&self, friendnumber: i32) -> Result<bool, Disconnected> {
+        self.friend_exists_async(friendnumber).wait()
+    }
+
+    /// Asynchronous version of `friend_exists`
+    #[inline]
+    pub fn friend_exists_async(&self, friendnumber: i32)
+            -> Reply<Result<bool, Disconnected>> {
+        forward!(self, backend::Control::FriendExists, (friendnumber),=>)
     }
 
     /// Send a message to the friend
     #[inline]
     pub fn send_message(&self, friendnumber: i32,
-                        msg: String) -> Result<u32, ()> {
+                        msg: String) -> Result<u32, MessageError> {
+        self.send_message_async(friendnumber, msg).wait()
+    }
+
+    /// Asynchronous version of `send_message`
+    #[inline]
+    pub fn send_message_async(&self, friendnumber: i32,
+                              msg: String) -> Reply<Result<u32, MessageError>> {
         forward!(self, backend::Control::SendMessage, (friendnumber, msg), ->)
     }
 
     /// Send an action message to the friend
     #[inline]
-    pub fn send_action(&self, friendnumber: i32, action: String) -> Result<u32, ()> {
+    pub fn send_action(&self, friendnumber: i32,
+                       action: String) -> Result<u32, MessageError> {
+        self.send_action_async(friendnumber, action).wait()
+    }
+
+    /// Asynchronous version of `send_action`
+    #[inline]
+    pub fn send_action_async(&self, friendnumber: i32,
+                             action: String) -> Reply<Result<u32, MessageError>> {
         forward!(self, backend::Control::SendAction, (friendnumber, action), ->)
     }
 
     /// Set self nickname
     #[inline]
-    pub fn set_name(&self, name: String) -> Result<(),()> {
+    pub fn set_name(&self, name: String) -> Result<(), MessageError> {
+        self.set_name_async(name).wait()
+    }
+
+    /// Asynchronous version of `set_name`
+    #[inline]
+    pub fn set_name_async(&self, name: String) -> Reply<Result<(), MessageError>> {
         forward!(self, backend::Control::SetName, (name), ->)
     }
 
     /// Returns the self nickname
     #[inline]
-    pub fn get_self_name(&self) -> Result<String, ()> {
+    pub fn get_self_name(&self) -> Result<ToxString, MessageError> {
+        self.get_self_name_async().wait()
+    }
+
+    /// Asynchronous version of `get_self_name`
+    #[inline]
+    pub fn get_self_name_async(&self) -> Reply<Result<ToxString, MessageError>> {
         forward!(self, backend::Control::GetSelfName, ->)
     }
 
     /// Get the nickname of the friend
     #[inline]
-    pub fn get_name(&self, friendnumber: i32) -> Result<String, ()> {
+    pub fn get_name(&self, friendnumber: i32) -> Result<ToxString, FriendError> {
+        self.get_name_async(friendnumber).wait()
+    }
+
+    /// Asynchronous version of `get_name`
+    #[inline]
+    pub fn get_name_async(&self, friendnumber: i32)
+            -> Reply<Result<ToxString, FriendError>> {
         forward!(self, backend::Control::GetName, (friendnumber), ->)
     }
 
     /// Set self status message
     #[inline]
-    pub fn set_status_message(&self, status: String) -> Result<(),()> {
+    pub fn set_status_message(&self, status: String) -> Result<(), MessageError> {
+        self.set_status_message_async(status).wait()
+    }
+
+    /// Asynchronous version of `set_status_message`
+    #[inline]
+    pub fn set_status_message_async(&self, status: String)
+            -> Reply<Result<(), MessageError>> {
         forward!(self, backend::Control::SetStatusMessage, (status), ->)
     }
 
     /// Set self status (`None`, `Away` or `Busy`)
     #[inline]
-    pub fn set_user_status(&self, userstatus: UserStatus) -> Result<(), ()> {
+    pub fn set_user_status(&self, userstatus: UserStatus) -> Result<(), MessageError> {
+        self.set_user_status_async(userstatus).wait()
+    }
+
+    /// Asynchronous version of `set_user_status`
+    #[inline]
+    pub fn set_user_status_async(&self, userstatus: UserStatus)
+            -> Reply<Result<(), MessageError>> {
         forward!(self, backend::Control::SetUserStatus, (userstatus), ->)
     }
 
     /// Get the status message of the friend
     #[inline]
-    pub fn get_status_message(&self, friendnumber: i32) -> Result<String, ()> {
+    pub fn get_status_message(&self,
+                              friendnumber: i32) -> Result<ToxString, FriendError> {
+        self.get_status_message_async(friendnumber).wait()
+    }
+
+    /// Asynchronous version of `get_status_message`
+    #[inline]
+    pub fn get_status_message_async(&self, friendnumber: i32)
+            -> Reply<Result<ToxString, FriendError>> {
         forward!(self, backend::Control::GetStatusMessage, (friendnumber), ->)
     }
 
     /// Get self status message
     #[inline]
-    pub fn get_self_status_message(&self) -> Result<String, ()> {
+    pub fn get_self_status_message(&self) -> Result<ToxString, MessageError> {
+        self.get_self_status_message_async().wait()
+    }
+
+    /// Asynchronous version of `get_self_status_message`
+    #[inline]
+    pub fn get_self_status_message_async(&self)
+            -> Reply<Result<ToxString, MessageError>> {
         forward!(self, backend::Control::GetSelfStatusMessage, ->)
     }
 
     /// Get status of the friend
     #[inline]
-    pub fn get_user_status(&self, friendnumber: i32) -> Result<UserStatus, ()> {
+    pub fn get_user_status(&self, friendnumber: i32) -> Result<UserStatus, FriendError> {
+        self.get_user_status_async(friendnumber).wait()
+    }
+
+    /// Asynchronous version of `get_user_status`
+    #[inline]
+    pub fn get_user_status_async(&self, friendnumber: i32)
+            -> Reply<Result<UserStatus, FriendError>> {
         forward!(self, backend::Control::GetUserStatus, (friendnumber), ->)
     }
 
     /// Get self status
     #[inline]
-    pub fn get_self_user_status(&self) -> Result<UserStatus, ()> {
+    pub fn get_self_user_status(&self) -> Result<UserStatus, MessageError> {
+        self.get_self_user_status_async().wait()
+    }
+
+    /// Asynchronous version of `get_self_user_status`
+    #[inline]
+    pub fn get_self_user_status_async(&self) -> Reply<Result<UserStatus, MessageError>> {
         forward!(self, backend::Control::GetSelfUserStatus, ->)
     }
 
     /// Return timestamp of last time the friend was seen online, or 0 if never seen
     #[inline]
-    pub fn get_last_online(&self, friendnumber: i32) -> Result<u64, ()> {
+    pub fn get_last_online(&self, friendnumber: i32) -> Result<u64, FriendError> {
+        self.get_last_online_async(friendnumber).wait()
+    }
+
+    /// Asynchronous version of `get_last_online`
+    #[inline]
+    pub fn get_last_online_async(&self, friendnumber: i32)
+            -> Reply<Result<u64, FriendError>> {
         forward!(self, backend::Control::GetLastOnline, (friendnumber), ->)
     }
 
@@ -529,148 +889,390 @@ impl ToxControl {
     /// off
     #[inline]
     pub fn set_user_is_typing(&self, friendnumber: i32,
-                              is_typing: bool) -> Result<(), ()> {
+                              is_typing: bool) -> Result<(), FriendError> {
+        self.set_user_is_typing_async(friendnumber, is_typing).wait()
+    }
+
+    /// Asynchronous version of `set_user_is_typing`
+    #[inline]
+    pub fn set_user_is_typing_async(&self, friendnumber: i32,
+                                    is_typing: bool) -> Reply<Result<(), FriendError>> {
         forward!(self, backend::Control::SetUserIsTyping, (friendnumber, is_typing), ->)
     }
 
     /// Get typing status of the given friend
     #[inline]
-    pub fn get_is_typing(&self, friendnumber: i32) -> bool {
-        forward!(self, backend::Control::GetIsTyping, (friendnumber), ->)
+    pub fn get_is_typing(&self, friendnumber: i32) -> Result<bool, Disconnected> {
+        self.get_is_typing_async(friendnumber).wait()
+    }
+
+    /// Asynchronous version of `get_is_typing`
+    #[inline]
+    pub fn get_is_typing_async(&self, friendnumber: i32)
+            -> Reply<Result<bool, Disconnected>> {
+        forward!(self, backend::Control::GetIsTyping, (friendnumber),=>)
     }
 
     /// Returns the number of friends
     #[inline]
-    pub fn count_friendlist(&self) -> u32 {
-        forward!(self, backend::Control::CountFriendlist, ->)
+    pub fn count_friendlist(&self) -> Result<u32, Disconnected> {
+        self.count_friendlist_async().wait()
+    }
+
+    /// Asynchronous version of `count_friendlist`
+    #[inline]
+    pub fn count_friendlist_async(&self) -> Reply<Result<u32, Disconnected>> {
+        forward!(self, backend::Control::CountFriendlist,=>)
     }
 
     /// Returns the number of chats
     #[inline]
-    pub fn count_chatlist(&self) -> u32 {
-        forward!(self, backend::Control::CountChatlist, ->)
+    pub fn count_chatlist(&self) -> Result<u32, Disconnected> {
+        self.count_chatlist_async().wait()
+    }
+
+    /// Asynchronous version of `count_chatlist`
+    #[inline]
+    pub fn count_chatlist_async(&self) -> Reply<Result<u32, Disconnected>> {
+        forward!(self, backend::Control::CountChatlist,=>)
     }
 
     /// Get the number of online friends
     #[inline]
-    pub fn get_num_online_friends(&self) -> u32 {
-        forward!(self, backend::Control::GetNumOnlineFriends, ->)
+    pub fn get_num_online_friends(&self) -> Result<u32, Disconnected> {
+        self.get_num_online_friends_async().wait()
+    }
+
+    /// Asynchronous version of `get_num_online_friends`
+    #[inline]
+    pub fn get_num_online_friends_async(&self) -> Reply<Result<u32, Disconnected>> {
+        forward!(self, backend::Control::GetNumOnlineFriends,=>)
     }
 
     /// Get the Vec of valid friend IDs
     #[inline]
-    pub fn get_friendlist(&self) -> Vec<i32> {
-        forward!(self, backend::Control::GetFriendlist, ->)
+    pub fn get_friendlist(&self) -> Result<Vec<i32>, Disconnected> {
+        self.get_friendlist_async().wait()
+    }
+
+    /// Asynchronous version of `get_friendlist`
+    #[inline]
+    pub fn get_friendlist_async(&self) -> Reply<Result<Vec<i32>, Disconnected>> {
+        forward!(self, backend::Control::GetFriendlist,=>)
     }
 
     /// Get self nospam
     #[inline]
-    pub fn get_nospam(&self) -> [u8; 4] {
-        forward!(self, backend::Control::GetNospam, ->)
+    pub fn get_nospam(&self) -> Result<[u8; 4], Disconnected> {
+        self.get_nospam_async().wait()
+    }
+
+    /// Asynchronous version of `get_nospam`
+    #[inline]
+    pub fn get_nospam_async(&self) -> Reply<Result<[u8; 4], Disconnected>> {
+        forward!(self, backend::Control::GetNospam,=>)
     }
 
     /// Set self nospam
     #[inline]
-    pub fn set_nospam(&self, nospam: [u8; 4]) {
+    pub fn set_nospam(&self, nospam: [u8; 4]) -> Result<(), Disconnected> {
         forward!(self, backend::Control::SetNospam, (nospam))
     }
 
+    /// Get the long-term keypair
+    #[inline]
+    pub fn get_keys(&self) -> Result<(Box<PublicKey>, Box<SecretKey>), Disconnected> {
+        self.get_keys_async().wait()
+    }
+
+    /// Asynchronous version of `get_keys`
+    #[inline]
+    pub fn get_keys_async(&self)
+            -> Reply<Result<(Box<PublicKey>, Box<SecretKey>), Disconnected>> {
+        forward!(self, backend::Control::GetKeys,=>)
+    }
+
     /// Create a new groupchat, returns groupchat number
     #[inline]
-    pub fn add_groupchat(&self) -> Result<i32, ()> {
+    pub fn add_groupchat(&self) -> Result<i32, GroupError> {
+        self.add_groupchat_async().wait()
+    }
+
+    /// Asynchronous version of `add_groupchat`
+    #[inline]
+    pub fn add_groupchat_async(&self) -> Reply<Result<i32, GroupError>> {
         forward!(self, backend::Control::AddGroupchat, ->)
     }
 
     /// Leave the groupchat
     #[inline]
-    pub fn del_groupchat(&self, groupnumber: i32) -> Result<(),()> {
+    pub fn del_groupchat(&self, groupnumber: i32) -> Result<(), GroupError> {
+        self.del_groupchat_async(groupnumber).wait()
+    }
+
+    /// Asynchronous version of `del_groupchat`
+    #[inline]
+    pub fn del_groupchat_async(&self, groupnumber: i32) -> Reply<Result<(), GroupError>> {
         forward!(self, backend::Control::DelGroupchat, (groupnumber), ->)
     }
 
     /// Returns the name of peer with given peer number in the groupchat
     #[inline]
     pub fn group_peername(&self, groupnumber: i32,
-                          peernumber: i32) -> Result<String, ()> {
+                          peernumber: i32) -> Result<ToxString, GroupError> {
+        self.group_peername_async(groupnumber, peernumber).wait()
+    }
+
+    /// Asynchronous version of `group_peername`
+    #[inline]
+    pub fn group_peername_async(&self, groupnumber: i32,
+                                peernumber: i32)
+            -> Reply<Result<ToxString, GroupError>> {
         forward!(self, backend::Control::GroupPeername, (groupnumber, peernumber), ->)
     }
 
+    /// Returns the public key of the peer with given peer number in the groupchat.
+    /// Unlike peer numbers, public keys stay the same when peers join or leave
+    #[inline]
+    pub fn group_peer_public_key(&self, groupnumber: i32,
+                                 peernumber: i32) -> Result<Box<ClientId>, GroupError> {
+        self.group_peer_public_key_async(groupnumber, peernumber).wait()
+    }
+
+    /// Asynchronous version of `group_peer_public_key`
+    #[inline]
+    pub fn group_peer_public_key_async(&self, groupnumber: i32, peernumber: i32)
+            -> Reply<Result<Box<ClientId>, GroupError>> {
+        forward!(self, backend::Control::GroupPeerPublicKey, (groupnumber, peernumber), ->)
+    }
+
+    /// Returns `true` if the peer with given peer number in the groupchat is us.
+    /// Otherwise, returns `false`
+    #[inline]
+    pub fn group_peer_is_ours(&self, groupnumber: i32,
+                              peernumber: i32) -> Result<bool, Disconnected> {
+        self.group_peer_is_ours_async(groupnumber, peernumber).wait()
+    }
+
+    /// Asynchronous version of `group_peer_is_ours`
+    #[inline]
+    pub fn group_peer_is_ours_async(&self, groupnumber: i32, peernumber: i32)
+            -> Reply<Result<bool, Disconnected>> {
+        forward!(self, backend::Control::GroupPeerIsOurs, (groupnumber, peernumber),=>)
+    }
+
     /// Invite the friend to the groupchat
     #[inline]
-    pub fn invite_friend(&self, friendnumber: i32, groupnumber: i32) -> Result<(), ()> {
+    pub fn invite_friend(&self, friendnumber: i32,
+                         groupnumber: i32) -> Result<(), GroupError> {
+        self.invite_friend_async(friendnumber, groupnumber).wait()
+    }
+
+    /// Asynchronous version of `invite_friend`
+    #[inline]
+    pub fn invite_friend_async(&self, friendnumber: i32,
+                               groupnumber: i32) -> Reply<Result<(), GroupError>> {
         forward!(self, backend::Control::InviteFriend, (friendnumber, groupnumber), ->)
     }
 
     /// Join a groupchat using `data` obtained by `GroupInvite` event
     #[inline]
-    pub fn join_groupchat(&self, friendnumber: i32, data: Vec<u8>) -> Result<i32, ()> {
+    pub fn join_groupchat(&self, friendnumber: i32,
+                          data: Vec<u8>) -> Result<i32, GroupError> {
+        self.join_groupchat_async(friendnumber, data).wait()
+    }
+
+    /// Asynchronous version of `join_groupchat`
+    #[inline]
+    pub fn join_groupchat_async(&self, friendnumber: i32,
+                                data: Vec<u8>) -> Reply<Result<i32, GroupError>> {
         forward!(self, backend::Control::JoinGroupchat, (friendnumber, data), ->)
     }
 
     /// Send a message to the groupchat
     #[inline]
     pub fn group_message_send(&self, groupnumber: i32,
-                              message: String) -> Result<(), ()> {
+                              message: String) -> Result<(), GroupError> {
+        self.group_message_send_async(groupnumber, message).wait()
+    }
+
+    /// Asynchronous version of `group_message_send`
+    #[inline]
+    pub fn group_message_send_async(&self, groupnumber: i32,
+                                    message: String) -> Reply<Result<(), GroupError>> {
         forward!(self, backend::Control::GroupMessageSend, (groupnumber, message), ->)
     }
 
     /// Send an action message to the groupchat
     #[inline]
-    pub fn group_action_send(&self, groupnumber: i32, action: String) -> Result<(), ()> {
+    pub fn group_action_send(&self, groupnumber: i32,
+                             action: String) -> Result<(), GroupError> {
+        self.group_action_send_async(groupnumber, action).wait()
+    }
+
+    /// Asynchronous version of `group_action_send`
+    #[inline]
+    pub fn group_action_send_async(&self, groupnumber: i32,
+                                   action: String) -> Reply<Result<(), GroupError>> {
         forward!(self, backend::Control::GroupActionSend, (groupnumber, action), ->)
     }
 
+    /// Set the title of the groupchat
+    #[inline]
+    pub fn group_set_title(&self, groupnumber: i32,
+                           title: String) -> Result<(), GroupError> {
+        self.group_set_title_async(groupnumber, title).wait()
+    }
+
+    /// Asynchronous version of `group_set_title`
+    #[inline]
+    pub fn group_set_title_async(&self, groupnumber: i32,
+                                 title: String) -> Reply<Result<(), GroupError>> {
+        forward!(self, backend::Control::GroupSetTitle, (groupnumber, title), ->)
+    }
+
+    /// Returns the title of the groupchat
+    #[inline]
+    pub fn group_get_title(&self, groupnumber: i32) -> Result<ToxString, GroupError> {
+        self.group_get_title_async(groupnumber).wait()
+    }
+
+    /// Asynchronous version of `group_get_title`
+    #[inline]
+    pub fn group_get_title_async(&self, groupnumber: i32)
+            -> Reply<Result<ToxString, GroupError>> {
+        forward!(self, backend::Control::GroupGetTitle, (groupnumber), ->)
+    }
+
     /// Returns number of peers in the groupchat
     #[inline]
-    pub fn group_number_peers(&self, groupnumber: i32) -> Result<i32, ()> {
+    pub fn group_number_peers(&self, groupnumber: i32) -> Result<i32, GroupError> {
+        self.group_number_peers_async(groupnumber).wait()
+    }
+
+    /// Asynchronous version of `group_number_peers`
+    #[inline]
+    pub fn group_number_peers_async(&self, groupnumber: i32)
+            -> Reply<Result<i32, GroupError>> {
         forward!(self, backend::Control::GroupNumberPeers, (groupnumber), ->)
     }
 
     /// Returns list of all peer names in the groupchat
     #[inline]
     pub fn group_get_names(&self,
-                           groupnumber: i32) -> Result<Vec<Option<String>>, ()> {
+                           groupnumber: i32) -> Result<Vec<ToxString>, GroupError> {
+        self.group_get_names_async(groupnumber).wait()
+    }
+
+    /// Asynchronous version of `group_get_names`
+    #[inline]
+    pub fn group_get_names_async(&self, groupnumber: i32)
+            -> Reply<Result<Vec<ToxString>, GroupError>> {
         forward!(self, backend::Control::GroupGetNames, (groupnumber), ->)
     }
 
-    /// Returns the Vec of all valid group IDs
+    /// Returns the type of the groupchat
+    #[inline]
+    pub fn group_get_type(&self, groupnumber: i32) -> Result<GroupchatType, GroupError> {
+        self.group_get_type_async(groupnumber).wait()
+    }
+
+    /// Asynchronous version of `group_get_type`
+    #[inline]
+    pub fn group_get_type_async(&self, groupnumber: i32)
+            -> Reply<Result<GroupchatType, GroupError>> {
+        forward!(self, backend::Control::GroupGetType, (groupnumber), ->)
+    }
+
+    /// Returns the Vec of all valid group IDs together with the type of the group
+    #[inline]
+    pub fn get_chatlist(&self) -> Result<Vec<(i32, GroupchatType)>, Disconnected> {
+        self.get_chatlist_async().wait()
+    }
+
+    /// Asynchronous version of `get_chatlist`
+    #[inline]
+    pub fn get_chatlist_async(&self)
+            -> Reply<Result<Vec<(i32, GroupchatType)>, Disconnected>> {
+        forward!(self, backend::Control::GetChatlist,=>)
+    }
+
     #[inline]
-    pub fn get_chatlist(&self) -> Vec<i32> {
-        forward!(self, backend::Control::GetChatlist, ->)
+    pub fn set_avatar(&self, format: AvatarFormat,
+                      data: Vec<u8>) -> Result<(), AvatarError> {
+        self.set_avatar_async(format, data).wait()
     }
 
+    /// Asynchronous version of `set_avatar`
     #[inline]
-    pub fn set_avatar(&self, format: AvatarFormat, data: Vec<u8>) -> Result<(), ()> {
+    pub fn set_avatar_async(&self, format: AvatarFormat,
+                            data: Vec<u8>) -> Reply<Result<(), AvatarError>> {
         forward!(self, backend::Control::SetAvatar, (format, data), ->)
     }
 
     #[inline]
-    pub fn unset_avatar(&self) {
+    pub fn unset_avatar(&self) -> Result<(), Disconnected> {
         forward!(self, backend::Control::UnsetAvatar)
     }
 
     #[inline]
-    pub fn get_self_avatar(&self) -> Result<(AvatarFormat, Vec<u8>, Hash), ()> {
+    pub fn get_self_avatar(&self) -> Result<(AvatarFormat, Vec<u8>, Hash), AvatarError> {
+        self.get_self_avatar_async().wait()
+    }
+
+    /// Asynchronous version of `get_self_avatar`
+    #[inline]
+    pub fn get_self_avatar_async(&self)
+            -> Reply<Result<(AvatarFormat, Vec<u8>, Hash), AvatarError>> {
         forward!(self, backend::Control::GetSelfAvatar, ->)
     }
 
     #[inline]
-    pub fn request_avatar_info(&self, friendnumber: i32) -> Result<(), ()> {
+    pub fn request_avatar_info(&self, friendnumber: i32) -> Result<(), AvatarError> {
+        self.request_avatar_info_async(friendnumber).wait()
+    }
+
+    /// Asynchronous version of `request_avatar_info`
+    #[inline]
+    pub fn request_avatar_info_async(&self, friendnumber: i32)
+            -> Reply<Result<(), AvatarError>> {
         forward!(self, backend::Control::RequestAvatarInfo, (friendnumber), ->)
     }
 
     #[inline]
-    pub fn send_avatar_info(&self, friendnumber: i32) -> Result<(), ()> {
+    pub fn send_avatar_info(&self, friendnumber: i32) -> Result<(), AvatarError> {
+        self.send_avatar_info_async(friendnumber).wait()
+    }
+
+    /// Asynchronous version of `send_avatar_info`
+    #[inline]
+    pub fn send_avatar_info_async(&self, friendnumber: i32)
+            -> Reply<Result<(), AvatarError>> {
         forward!(self, backend::Control::SendAvatarInfo, (friendnumber), ->)
     }
 
     #[inline]
-    pub fn request_avatar_data(&self, friendnumber: i32) -> Result<(), ()> {
+    pub fn request_avatar_data(&self, friendnumber: i32) -> Result<(), AvatarError> {
+        self.request_avatar_data_async(friendnumber).wait()
+    }
+
+    /// Asynchronous version of `request_avatar_data`
+    #[inline]
+    pub fn request_avatar_data_async(&self, friendnumber: i32)
+            -> Reply<Result<(), AvatarError>> {
         forward!(self, backend::Control::RequestAvatarData, (friendnumber), ->)
     }
 
     #[inline]
     pub fn new_file_sender(&self, friendnumber: i32, filesize: u64,
-                           filename: PathBuf) -> Result<i32, ()> {
+                           filename: PathBuf) -> Result<i32, FileError> {
+        self.new_file_sender_async(friendnumber, filesize, filename).wait()
+    }
+
+    /// Asynchronous version of `new_file_sender`
+    #[inline]
+    pub fn new_file_sender_async(&self, friendnumber: i32, filesize: u64,
+                                 filename: PathBuf) -> Reply<Result<i32, FileError>> {
         forward!(self, backend::Control::NewFileSender,
                  (friendnumber, filesize, filename), ->)
     }
@@ -678,72 +1280,318 @@ impl ToxControl {
     #[inline]
     pub fn file_send_control(&self, friendnumber: i32, send_receive: TransferType,
                              filenumber: u8, message_id: u8,
-                             data: Vec<u8>) -> Result<(), ()> {
+                             data: Vec<u8>) -> Result<(), FileError> {
+        self.file_send_control_async(friendnumber, send_receive, filenumber, message_id,
+                                     data).wait()
+    }
+
+    /// Asynchronous version of `file_send_control`
+    #[inline]
+    pub fn file_send_control_async(&self, friendnumber: i32, send_receive: TransferType,
+                                   filenumber: u8, message_id: u8,
+                                   data: Vec<u8>) -> Reply<Result<(), FileError>> {
         forward!(self, backend::Control::FileSendControl,
                  (friendnumber, send_receive, filenumber, message_id, data), ->)
     }
 
     #[inline]
     pub fn file_send_data(&self, friendnumber: i32, filenumber: u8,
-                          data: Vec<u8>) -> Result<(), ()> {
+                          data: Vec<u8>) -> Result<(), FileError> {
+        self.file_send_data_async(friendnumber, filenumber, data).wait()
+    }
+
+    /// Asynchronous version of `file_send_data`
+    #[inline]
+    pub fn file_send_data_async(&self, friendnumber: i32, filenumber: u8,
+                                data: Vec<u8>) -> Reply<Result<(), FileError>> {
         forward!(self, backend::Control::FileSendData,
                  (friendnumber, filenumber, data), ->)
     }
 
     #[inline]
-    pub fn file_data_size(&self, friendnumber: i32) -> Result<i32, ()> {
+    pub fn file_data_size(&self, friendnumber: i32) -> Result<i32, FileError> {
+        self.file_data_size_async(friendnumber).wait()
+    }
+
+    /// Asynchronous version of `file_data_size`
+    #[inline]
+    pub fn file_data_size_async(&self, friendnumber: i32)
+            -> Reply<Result<i32, FileError>> {
         forward!(self, backend::Control::FileDataSize, (friendnumber), ->)
     }
 
     #[inline]
     pub fn file_data_remaining(&self, friendnumber: i32, filenumber: u8,
-                               send_receive: TransferType) -> Result<u64, ()> {
+                               send_receive: TransferType) -> Result<u64, FileError> {
+        self.file_data_remaining_async(friendnumber, filenumber, send_receive).wait()
+    }
+
+    /// Asynchronous version of `file_data_remaining`
+    #[inline]
+    pub fn file_data_remaining_async(&self, friendnumber: i32, filenumber: u8,
+                                     send_receive: TransferType)
+            -> Reply<Result<u64, FileError>> {
         forward!(self, backend::Control::FileDataRemaining,
                  (friendnumber, filenumber, send_receive), ->)
     }
 
+    /// Register the packet ids in `ids` so that lossy packets with these ids received
+    /// from the friend are delivered as `LossyPacket` events
+    #[inline]
+    pub fn register_lossy_packets(&self, friendnumber: i32,
+                                  ids: Range<u8>) -> Result<(), PacketError> {
+        self.register_lossy_packets_async(friendnumber, ids).wait()
+    }
+
+    /// Asynchronous version of `register_lossy_packets`
+    #[inline]
+    pub fn register_lossy_packets_async(&self, friendnumber: i32, ids: Range<u8>)
+            -> Reply<Result<(), PacketError>> {
+        forward!(self, backend::Control::RegisterLossyPackets, (friendnumber, ids), ->)
+    }
+
+    /// Register the packet ids in `ids` so that lossless packets with these ids
+    /// received from the friend are delivered as `LosslessPacket` events
+    #[inline]
+    pub fn register_lossless_packets(&self, friendnumber: i32,
+                                     ids: Range<u8>) -> Result<(), PacketError> {
+        self.register_lossless_packets_async(friendnumber, ids).wait()
+    }
+
+    /// Asynchronous version of `register_lossless_packets`
+    #[inline]
+    pub fn register_lossless_packets_async(&self, friendnumber: i32, ids: Range<u8>)
+            -> Reply<Result<(), PacketError>> {
+        forward!(self, backend::Control::RegisterLosslessPackets, (friendnumber, ids), ->)
+    }
+
+    /// Send a lossy packet with the given id to the friend. `data` must not contain
+    /// the id byte
+    #[inline]
+    pub fn send_lossy_packet(&self, friendnumber: i32, id: u8,
+                             data: Vec<u8>) -> Result<(), PacketError> {
+        self.send_lossy_packet_async(friendnumber, id, data).wait()
+    }
+
+    /// Asynchronous version of `send_lossy_packet`
+    #[inline]
+    pub fn send_lossy_packet_async(&self, friendnumber: i32, id: u8,
+                                   data: Vec<u8>) -> Reply<Result<(), PacketError>> {
+        forward!(self, backend::Control::SendLossyPacket, (friendnumber, id, data), ->)
+    }
+
+    /// Send a lossless packet with the given id to the friend. `data` must not contain
+    /// the id byte
+    #[inline]
+    pub fn send_lossless_packet(&self, friendnumber: i32, id: u8,
+                                data: Vec<u8>) -> Result<(), PacketError> {
+        self.send_lossless_packet_async(friendnumber, id, data).wait()
+    }
+
+    /// Asynchronous version of `send_lossless_packet`
+    #[inline]
+    pub fn send_lossless_packet_async(&self, friendnumber: i32, id: u8,
+                                      data: Vec<u8>) -> Reply<Result<(), PacketError>> {
+        forward!(self, backend::Control::SendLosslessPacket, (friendnumber, id, data), ->)
+    }
+
     /// Bootstrap from the given (address, port, ClientId)
     #[inline]
     pub fn bootstrap_from_address(&self, address: String, port: u16,
-                                  public_key: Box<ClientId>) -> Result<(), ()> {
+                                  public_key: Box<ClientId>) -> Result<(), BootstrapError> {
+        self.bootstrap_from_address_async(address, port, public_key).wait()
+    }
+
+    /// Asynchronous version of `bootstrap_from_address`
+    #[inline]
+    pub fn bootstrap_from_address_async(&self, address: String, port: u16,
+                                        public_key: Box<ClientId>)
+            -> Reply<Result<(), BootstrapError>> {
         forward!(self, backend::Control::BootstrapFromAddress,
                  (address, port, public_key), ->)
     }
 
+    /// Use the given (address, port, ClientId) as a TCP relay
+    #[inline]
+    pub fn add_tcp_relay(&self, address: String, port: u16,
+                         public_key: Box<ClientId>) -> Result<(), BootstrapError> {
+        self.add_tcp_relay_async(address, port, public_key).wait()
+    }
+
+    /// Asynchronous version of `add_tcp_relay`
+    #[inline]
+    pub fn add_tcp_relay_async(&self, address: String, port: u16,
+                               public_key: Box<ClientId>)
+            -> Reply<Result<(), BootstrapError>> {
+        forward!(self, backend::Control::AddTcpRelay, (address, port, public_key), ->)
+    }
+
+    /// Bootstrap from the node via UDP and add it as a TCP relay on all of its TCP
+    /// ports. Succeeds if at least one of these operations succeeds
+    #[inline]
+    pub fn bootstrap(&self, node: Box<BootstrapNode>) -> Result<(), BootstrapError> {
+        self.bootstrap_async(node).wait()
+    }
+
+    /// Asynchronous version of `bootstrap`
+    #[inline]
+    pub fn bootstrap_async(&self, node: Box<BootstrapNode>)
+            -> Reply<Result<(), BootstrapError>> {
+        forward!(self, backend::Control::Bootstrap, (node), ->)
+    }
+
     /// Returns `true` if connected to DHT. Otherwise, returns `false`
     #[inline]
-    pub fn is_connected(&self) -> bool {
-        forward!(self, backend::Control::Isconnected, ->)
+    pub fn is_connected(&self) -> Result<bool, Disconnected> {
+        self.is_connected_async().wait()
+    }
+
+    /// Asynchronous version of `is_connected`
+    #[inline]
+    pub fn is_connected_async(&self) -> Reply<Result<bool, Disconnected>> {
+        forward!(self, backend::Control::Isconnected,=>)
+    }
+
+    /// Returns the number of events that were dropped because the event receiver
+    /// did not keep up. See `ToxOptions::overflow_policy`
+    #[inline]
+    pub fn dropped_events(&self) -> Result<u64, Disconnected> {
+        self.dropped_events_async().wait()
+    }
+
+    /// Asynchronous version of `dropped_events`
+    #[inline]
+    pub fn dropped_events_async(&self) -> Reply<Result<u64, Disconnected>> {
+        forward!(self, backend::Control::DroppedEvents,=>)
+    }
+
+    /// Returns when we last connected to the DHT in seconds since the epoch.
+    /// `None` if we haven't been connected yet
+    #[inline]
+    pub fn last_connect(&self) -> Result<Option<u64>, Disconnected> {
+        self.last_connect_async().wait()
+    }
+
+    /// Asynchronous version of `last_connect`
+    #[inline]
+    pub fn last_connect_async(&self) -> Reply<Result<Option<u64>, Disconnected>> {
+        forward!(self, backend::Control::LastConnect,=>)
+    }
+
+    /// Returns when we last lost the connection to the DHT in seconds since the
+    /// epoch. `None` if we haven't lost it yet
+    #[inline]
+    pub fn last_disconnect(&self) -> Result<Option<u64>, Disconnected> {
+        self.last_disconnect_async().wait()
+    }
+
+    /// Asynchronous version of `last_disconnect`
+    #[inline]
+    pub fn last_disconnect_async(&self) -> Reply<Result<Option<u64>, Disconnected>> {
+        forward!(self, backend::Control::LastDisconnect,=>)
+    }
+
+    /// Returns `true` if the backend thread still answers calls. Doesn't wait for the
+    /// backend. Once this returns `false`, all methods return `Disconnected` errors
+    /// and the instance has to be recreated
+    #[inline]
+    pub fn is_alive(&self) -> bool {
+        self.alive.recv_async() != Err(comm::Error::Disconnected)
     }
 
     /// Create a new tox instance
     #[inline]
     pub fn new(mut opts: ToxOptions) -> Option<(ToxControl, CoreEvents)> {
-        match backend::Backend::new(&mut opts.txo) {
-            Some((ctrl, events)) => Some((ToxControl { control: ctrl }, events)),
+        match backend::Backend::new(&mut opts) {
+            Some((ctrl, events, alive, thread)) => {
+                Some((ToxControl { control: ctrl, thread: thread, alive: alive }, events))
+            },
             None => None,
         }
     }
 
+    /// Stop the instance and wait until the backend thread has exited. An AV session
+    /// created by `av` is torn down first. Returns the final save data
+    #[inline]
+    pub fn shutdown(self) -> Result<Vec<u8>, Disconnected> {
+        let ToxControl { control, thread, .. } = self;
+        let (snd, rcv) = spsc::one_space::new();
+        let res = match control.send(backend::Control::Shutdown(snd)) {
+            Ok(()) => rcv.recv_sync().map_err(|_| Disconnected),
+            Err(_) => Err(Disconnected),
+        };
+        drop(control);
+        let _ = thread.join();
+        res
+    }
+
     /// Returns a tox data that should be saved in the tox file
     #[inline]
-    pub fn save(&self) -> Vec<u8> {
-        forward!(self, backend::Control::Save, ->)
+    pub fn save(&self) -> Result<Vec<u8>, Disconnected> {
+        self.save_async().wait()
+    }
+
+    /// Asynchronous version of `save`
+    #[inline]
+    pub fn save_async(&self) -> Reply<Result<Vec<u8>, Disconnected>> {
+        forward!(self, backend::Control::Save,=>)
     }
 
     /// Load instance data from Vec
     #[inline]
-    pub fn load(&self, data: Vec<u8>) -> Result<(), ()> {
+    pub fn load(&self, data: Vec<u8>) -> Result<(), LoadError> {
+        self.load_async(data).wait()
+    }
+
+    /// Asynchronous version of `load`
+    #[inline]
+    pub fn load_async(&self, data: Vec<u8>) -> Reply<Result<(), LoadError>> {
         forward!(self, backend::Control::Load, (data), ->)
     }
 
+    /// Returns the tox data encrypted with `passphrase`. The encryption happens on
+    /// the calling thread
+    pub fn save_encrypted(&self, passphrase: &str) -> Result<Vec<u8>, SaveError> {
+        let mut data = try!(self.save());
+        let res = encryptsave::encrypt(&data, passphrase)
+                      .map_err(|_| SaveError::Encryption);
+        util::zero_memory(&mut data);
+        res
+    }
+
+    /// Load instance data that may be encrypted with `passphrase`. The decryption
+    /// happens on the calling thread
+    pub fn load_encrypted(&self, data: Vec<u8>,
+                          passphrase: &str) -> Result<(), LoadError> {
+        let data = match encryptsave::decrypt(&data, passphrase) {
+            Ok(plain) => plain,
+            Err(DecryptError::NotEncrypted) => data,
+            Err(DecryptError::Failed) => return Err(LoadError::Decryption),
+        };
+        self.load(data)
+    }
+
+    #[inline]
+    pub unsafe fn raw(&self) -> Result<*mut ll::Tox, Disconnected> {
+        self.raw_async().wait()
+    }
+
+    /// Asynchronous version of `raw`
+    #[inline]
+    pub unsafe fn raw_async(&self) -> Reply<Result<*mut ll::Tox, Disconnected>> {
+        forward!(self, backend::Control::Raw,=>)
+    }
+
     #[inline]
-    pub unsafe fn raw(&self) -> *mut ll::Tox {
-        forward!(self, backend::Control::Raw, ->)
+    pub fn av(&self,
+              max_calls: i32) -> Result<Option<(AvControl, AvEvents)>, Disconnected> {
+        self.av_async(max_calls).wait()
     }
 
+    /// Asynchronous version of `av`
     #[inline]
-    pub fn av(&self, max_calls: i32) -> Option<(AvControl, AvEvents)> {
-        forward!(self, backend::Control::Av, (max_calls), ->)
+    pub fn av_async(&self, max_calls: i32)
+            -> Reply<Result<Option<(AvControl, AvEvents)>, Disconnected>> {
+        forward!(self, backend::Control::Av, (max_calls),=>)
     }
 }
@@ -0,0 +1,122 @@
+//! `tox:` URIs.
+
+use std::{fmt};
+use std::str::{FromStr};
+
+use core::{Address, ParseError};
+
+/// A `tox:` URI of the form `tox:<address>[?message=<message>]`
+#[derive(Clone, PartialEq, Debug)]
+pub struct ToxUri {
+    pub address: Address,
+    /// The message to send with the friend request
+    pub message: Option<String>,
+}
+
+impl ToxUri {
+    #[inline]
+    pub fn new(address: Address) -> ToxUri {
+        ToxUri { address: address, message: None }
+    }
+}
+
+/// Returns `true` if `s` starts with the lowercase ASCII string `prefix`, ignoring
+/// case
+fn has_prefix(s: &str, prefix: &str) -> bool {
+    s.len() >= prefix.len() && s.bytes().zip(prefix.bytes()).all(|(a, b)| {
+        a == b || (a >= b'A' && a <= b'Z' && a + 32 == b)
+    })
+}
+
+/// Removes a `tox:` or `tox://` prefix. The scheme is case insensitive
+pub fn strip_scheme(s: &str) -> &str {
+    if has_prefix(s, "tox://") {
+        &s[6..]
+    } else if has_prefix(s, "tox:") {
+        &s[4..]
+    } else {
+        s
+    }
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                if i + 2 >= bytes.len() {
+                    return None;
+                }
+                let hi = (bytes[i+1] as char).to_digit(16);
+                let lo = (bytes[i+2] as char).to_digit(16);
+                match (hi, lo) {
+                    (Some(hi), Some(lo)) => out.push((hi * 16 + lo) as u8),
+                    _ => return None,
+                }
+                i += 3;
+            },
+            b'+' => {
+                out.push(b' ');
+                i += 1;
+            },
+            b => {
+                out.push(b);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn percent_encode(fmt: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    for &b in s.as_bytes().iter() {
+        match b {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'_' | b'.' | b'~' =>
+                try!(write!(fmt, "{}", b as char)),
+            _ => try!(write!(fmt, "%{:02X}", b)),
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for ToxUri {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(fmt, "tox:{}", self.address));
+        if let Some(ref msg) = self.message {
+            try!(fmt.write_str("?message="));
+            try!(percent_encode(fmt, msg));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ToxUri {
+    type Err = ParseError;
+
+    /// Parses a URI. The address is parsed with `Address::parse_tolerant` and
+    /// unknown parameters are ignored
+    fn from_str(s: &str) -> Result<ToxUri, ParseError> {
+        let (addr, query) = match s.find('?') {
+            Some(pos) => (&s[..pos], Some(&s[pos+1..])),
+            None => (s, None),
+        };
+        let mut uri = ToxUri::new(try!(Address::parse_tolerant(addr)));
+        if let Some(query) = query {
+            for param in query.trim().split('&') {
+                let (key, value) = match param.find('=') {
+                    Some(pos) => (&param[..pos], &param[pos+1..]),
+                    None => (param, ""),
+                };
+                if key == "message" {
+                    match percent_decode(value) {
+                        Some(msg) => uri.message = Some(msg),
+                        None => return Err(ParseError::BadUri),
+                    }
+                }
+            }
+        }
+        Ok(uri)
+    }
+}
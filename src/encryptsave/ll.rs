@@ -0,0 +1,13 @@
+#![allow(dead_code)]
+
+use libc::{c_int};
+
+#[link(name = "toxencryptsave")]
+extern {
+    pub fn tox_pass_encryption_extra_length() -> c_int;
+    pub fn tox_pass_encrypt(data: *const u8, data_len: u32, passphrase: *const u8,
+                            pplength: u32, out: *mut u8) -> c_int;
+    pub fn tox_pass_decrypt(data: *const u8, length: u32, passphrase: *const u8,
+                            pplength: u32, out: *mut u8) -> c_int;
+    pub fn tox_is_data_encrypted(data: *const u8) -> c_int;
+}
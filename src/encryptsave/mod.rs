@@ -0,0 +1,124 @@
+//! Passphrase encryption of save data.
+//!
+//! The data is encrypted with toxencryptsave and can be read by every client that
+//! supports encrypted profiles. The key is derived from the passphrase with scrypt,
+//! so encrypting and decrypting takes a noticeable amount of time.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use tox::core::*;
+//! use tox::encryptsave;
+//!
+//! let (tox, _events) = ToxControl::new(ToxOptions::new()).unwrap();
+//! let data = tox.save_encrypted("correct horse").unwrap();
+//! assert!(encryptsave::is_encrypted(&data));
+//! let data = encryptsave::change_passphrase(&data, "correct horse",
+//!                                           "battery staple").unwrap();
+//! tox.load_encrypted(data, "battery staple").unwrap();
+//! ```
+
+use std::{error, fmt};
+
+use util;
+
+pub mod ll;
+
+/// The first bytes of encrypted save data
+pub const MAGIC: &'static [u8] = b"toxEsave";
+
+/// The data could not be encrypted
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct EncryptError;
+
+impl error::Error for EncryptError {
+    fn description(&self) -> &str {
+        "data could not be encrypted"
+    }
+}
+
+/// Errors of `decrypt`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DecryptError {
+    /// The data is not encrypted
+    NotEncrypted,
+    /// The passphrase is wrong or the data has been corrupted
+    Failed,
+}
+
+impl error::Error for DecryptError {
+    fn description(&self) -> &str {
+        match *self {
+            DecryptError::NotEncrypted => "data is not encrypted",
+            DecryptError::Failed       => "wrong passphrase or corrupted data",
+        }
+    }
+}
+
+impl fmt::Display for EncryptError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(error::Error::description(self))
+    }
+}
+
+impl fmt::Display for DecryptError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(error::Error::description(self))
+    }
+}
+
+/// Returns the number of bytes encryption adds to the data
+pub fn extra_length() -> usize {
+    unsafe { ll::tox_pass_encryption_extra_length() as usize }
+}
+
+/// Returns `true` if `data` has been encrypted with `encrypt`
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.len() >= extra_length() && data.starts_with(MAGIC)
+}
+
+/// Encrypt `data` with a key derived from `passphrase`
+pub fn encrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>, EncryptError> {
+    let size = data.len() + extra_length();
+    let mut out = Vec::with_capacity(size);
+    let res = unsafe {
+        ll::tox_pass_encrypt(data.as_ptr(), data.len() as u32, passphrase.as_ptr(),
+                             passphrase.len() as u32, out.as_mut_ptr())
+    };
+    match res {
+        0 => {
+            unsafe { out.set_len(size); }
+            Ok(out)
+        },
+        _ => Err(EncryptError),
+    }
+}
+
+/// Decrypt data that has been encrypted with `encrypt`
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>, DecryptError> {
+    if !is_encrypted(data) {
+        return Err(DecryptError::NotEncrypted);
+    }
+    let mut out = Vec::with_capacity(data.len() - extra_length());
+    let res = unsafe {
+        ll::tox_pass_decrypt(data.as_ptr(), data.len() as u32, passphrase.as_ptr(),
+                             passphrase.len() as u32, out.as_mut_ptr())
+    };
+    match res {
+        n if n >= 0 && n as usize <= out.capacity() => {
+            unsafe { out.set_len(n as usize); }
+            Ok(out)
+        },
+        _ => Err(DecryptError::Failed),
+    }
+}
+
+/// Re-encrypt data that has been encrypted with `old` with the passphrase `new`.
+/// Returns `Failed` if the data cannot be decrypted or re-encrypted
+pub fn change_passphrase(data: &[u8], old: &str,
+                         new: &str) -> Result<Vec<u8>, DecryptError> {
+    let mut plain = try!(decrypt(data, old));
+    let res = encrypt(&plain, new).map_err(|_| DecryptError::Failed);
+    util::zero_memory(&mut plain);
+    res
+}
@@ -0,0 +1,182 @@
+//! Non-blocking access to the backend.
+//!
+//! The `*_async` methods of `ToxControl` queue the call without waiting for the
+//! backend thread and return a `Reply`. `CoreEvents` and `AvEvents` implement
+//! `Stream`. Replies and event receivers can be added to a `comm::select::Select`,
+//! so one thread can wait for replies, events and other channels at once, e.g., the
+//! ones of threads that watch sockets or timers.
+//!
+//! # Example
+//!
+//! ```no_run
+//! extern crate comm;
+//! extern crate tox;
+//!
+//! use comm::select::{Select};
+//! use tox::core::*;
+//! use tox::future::{Future, Stream, Poll};
+//!
+//! fn main() {
+//!     let (tox, mut events) = ToxControl::new(ToxOptions::new()).unwrap();
+//!     let mut name = tox.get_self_name_async();
+//!     let sel = Select::new();
+//!     sel.add(&events);
+//!     name.add_to(&sel);
+//!     loop {
+//!         sel.wait(&mut []);
+//!         while let Poll::Ready(Some(ev)) = events.poll() {
+//!             println!("{:?}", ev);
+//!         }
+//!         if let Poll::Ready(name) = name.poll() {
+//!             println!("{:?}", name);
+//!             break;
+//!         }
+//!     }
+//!     name.remove_from(&sel);
+//! }
+//! ```
+
+use comm::{self, spsc};
+use comm::select::{Select, Selectable};
+
+use core::{Event, CoreEvents, Disconnected};
+use av::{self, AvEvents};
+
+/// The state of a `Future` or `Stream`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Poll<T> {
+    Ready(T),
+    NotReady,
+}
+
+/// A value that will be available at some point in the future
+pub trait Future {
+    type Item;
+
+    /// Returns the value if it is available. Must not be called again after it has
+    /// returned `Ready`
+    fn poll(&mut self) -> Poll<Self::Item>;
+
+    /// Block until the value is available
+    fn wait(self) -> Self::Item;
+}
+
+/// A sequence of values that become available over time
+pub trait Stream {
+    type Item;
+
+    /// Returns the next value if it is available. `Ready(None)` means that the
+    /// stream has ended and no more values will become available
+    fn poll(&mut self) -> Poll<Option<Self::Item>>;
+}
+
+trait Receiver<T> {
+    /// Returns `None` if `block` is `false` and the reply has not arrived yet
+    fn recv(&mut self, block: bool) -> Option<T>;
+    fn add_to(&self, sel: &Select<'static>);
+    fn remove_from(&self, sel: &Select<'static>);
+    fn id(&self) -> usize;
+}
+
+struct Mapped<R: Send + 'static, T> {
+    rcv: spsc::one_space::Consumer<'static, R>,
+    map: fn(Result<R, Disconnected>) -> T,
+}
+
+impl<R: Send + 'static, T> Receiver<T> for Mapped<R, T> {
+    fn recv(&mut self, block: bool) -> Option<T> {
+        let res = match block {
+            true => self.rcv.recv_sync(),
+            false => self.rcv.recv_async(),
+        };
+        match res {
+            Ok(res) => Some((self.map)(Ok(res))),
+            Err(comm::Error::Disconnected) => Some((self.map)(Err(Disconnected))),
+            Err(_) => None,
+        }
+    }
+
+    fn add_to(&self, sel: &Select<'static>) {
+        sel.add(&self.rcv);
+    }
+
+    fn remove_from(&self, sel: &Select<'static>) {
+        sel.remove(&self.rcv);
+    }
+
+    fn id(&self) -> usize {
+        self.rcv.id()
+    }
+}
+
+/// The reply of the backend thread to a call
+pub struct Reply<T> {
+    inner: Box<Receiver<T> + 'static>,
+}
+
+impl<T: 'static> Reply<T> {
+    /// Create a reply that is received on `rcv`. If the call could not be sent to
+    /// the backend, the producer has been dropped with it and the reply resolves to
+    /// `Disconnected`. `map` turns what was received into the value of the future
+    pub fn new<R: Send + 'static>(rcv: spsc::one_space::Consumer<'static, R>,
+                                  map: fn(Result<R, Disconnected>) -> T) -> Reply<T> {
+        Reply { inner: Box::new(Mapped { rcv: rcv, map: map }) }
+    }
+}
+
+impl<T> Reply<T> {
+    /// Add the reply to `sel`. `Select::wait` returns once the reply has arrived or
+    /// the backend has stopped
+    pub fn add_to(&self, sel: &Select<'static>) {
+        self.inner.add_to(sel)
+    }
+
+    /// Remove the reply from `sel`
+    pub fn remove_from(&self, sel: &Select<'static>) {
+        self.inner.remove_from(sel)
+    }
+
+    /// Returns the id that `Select::wait` reports for the reply
+    pub fn id(&self) -> usize {
+        self.inner.id()
+    }
+}
+
+impl<T> Future for Reply<T> {
+    type Item = T;
+
+    fn poll(&mut self) -> Poll<T> {
+        match self.inner.recv(false) {
+            Some(res) => Poll::Ready(res),
+            None => Poll::NotReady,
+        }
+    }
+
+    fn wait(mut self) -> T {
+        self.inner.recv(true).unwrap()
+    }
+}
+
+impl Stream for CoreEvents {
+    type Item = Event;
+
+    fn poll(&mut self) -> Poll<Option<Event>> {
+        match self.recv_async() {
+            Ok(ev) => Poll::Ready(Some(ev)),
+            Err(comm::Error::Disconnected) => Poll::Ready(None),
+            Err(_) => Poll::NotReady,
+        }
+    }
+}
+
+impl Stream for AvEvents {
+    type Item = av::Event;
+
+    fn poll(&mut self) -> Poll<Option<av::Event>> {
+        match self.recv_async() {
+            Ok(ev) => Poll::Ready(Some(ev)),
+            Err(comm::Error::Disconnected) => Poll::Ready(None),
+            Err(_) => Poll::NotReady,
+        }
+    }
+}
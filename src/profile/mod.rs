@@ -0,0 +1,354 @@
+//! Tox profiles stored on disk.
+//!
+//! A `Profile` owns the `ToxControl` of an identity and the file it is stored in.
+//! Writes are atomic: the data is written to a temporary file which then replaces
+//! the profile. Older versions can be kept as `<path>.1`, `<path>.2`, etc. While a
+//! profile is open, `<path>.lock` is locked so that no other process can use the
+//! same identity.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::path::{Path};
+//! use tox::core::*;
+//! use tox::profile::{Profile, Trigger};
+//!
+//! let (mut profile, events) = Profile::open(Path::new("bot.tox"),
+//!                                           ToxOptions::new()).unwrap();
+//! profile.autosave(Trigger::FriendList);
+//! profile.autosave(Trigger::Interval(300));
+//! profile.keep_backups(3);
+//! while let Ok(ev) = events.recv_sync() {
+//!     // handle events
+//!     profile.poll().unwrap();
+//! }
+//! profile.close().unwrap();
+//! ```
+
+use std::{error, fmt, io};
+use std::ffi::{OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::{AsRawFd, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use libc::{c_int};
+
+use core::{ToxControl, ToxOptions, CoreEvents, LoadError, SaveError, Disconnected,
+           ToxString};
+use encryptsave;
+use util::{self, unix_time};
+
+const LOCK_EX: c_int = 2;
+const LOCK_NB: c_int = 4;
+
+extern {
+    fn flock(fd: c_int, operation: c_int) -> c_int;
+}
+
+/// Errors of the profile functions
+#[derive(Debug)]
+pub enum ProfileError {
+    /// Reading or writing a file failed
+    Io(io::Error),
+    /// The profile is used by another process
+    Locked,
+    /// The tox instance could not be created
+    Create,
+    /// The profile could not be loaded
+    Load(LoadError),
+    /// The profile could not be encrypted
+    Save(SaveError),
+    /// The backend thread has stopped
+    Disconnected,
+}
+
+impl error::Error for ProfileError {
+    fn description(&self) -> &str {
+        match *self {
+            ProfileError::Io(ref e)   => e.description(),
+            ProfileError::Locked      => "profile is used by another process",
+            ProfileError::Create      => "tox instance could not be created",
+            ProfileError::Load(ref e) => e.description(),
+            ProfileError::Save(ref e) => e.description(),
+            ProfileError::Disconnected => "backend thread has stopped",
+        }
+    }
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(error::Error::description(self))
+    }
+}
+
+impl From<io::Error> for ProfileError {
+    fn from(e: io::Error) -> ProfileError {
+        ProfileError::Io(e)
+    }
+}
+
+impl From<Disconnected> for ProfileError {
+    fn from(_: Disconnected) -> ProfileError {
+        ProfileError::Disconnected
+    }
+}
+
+impl From<LoadError> for ProfileError {
+    fn from(e: LoadError) -> ProfileError {
+        match e {
+            LoadError::Disconnected => ProfileError::Disconnected,
+            e => ProfileError::Load(e),
+        }
+    }
+}
+
+impl From<SaveError> for ProfileError {
+    fn from(e: SaveError) -> ProfileError {
+        match e {
+            SaveError::Disconnected => ProfileError::Disconnected,
+            e => ProfileError::Save(e),
+        }
+    }
+}
+
+/// When `Profile::poll` saves the profile
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Trigger {
+    /// A friend has been added or removed
+    FriendList,
+    /// Our name has changed
+    Name,
+    /// Our status message has changed
+    StatusMessage,
+    /// The profile hasn't been saved for this many seconds
+    Interval(u64),
+}
+
+/// The parts of the state that are watched by the triggers
+#[derive(Clone, PartialEq, Debug)]
+struct Snapshot {
+    friends: Vec<i32>,
+    name: Option<ToxString>,
+    status: Option<ToxString>,
+}
+
+impl Snapshot {
+    fn take(tox: &ToxControl) -> Result<Snapshot, Disconnected> {
+        Ok(Snapshot {
+            friends: try!(tox.get_friendlist()),
+            name: tox.get_self_name().ok(),
+            status: tox.get_self_status_message().ok(),
+        })
+    }
+}
+
+/// Returns `path` with `suffix` appended to the file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut s = OsString::new();
+    s.push(path.as_os_str());
+    s.push(suffix);
+    PathBuf::new(&s)
+}
+
+/// A tox identity that is stored in a file
+pub struct Profile {
+    path: PathBuf,
+    tox: ToxControl,
+    /// Holds the lock while the profile is open
+    lock: File,
+    passphrase: Option<String>,
+    triggers: Vec<Trigger>,
+    backups: usize,
+    last_save: u64,
+    snapshot: Snapshot,
+}
+
+impl Profile {
+    /// Open the profile at `path` and start a tox instance with it. A new identity
+    /// is created if the file doesn't exist; it is written on the first save
+    pub fn open(path: &Path,
+                opts: ToxOptions) -> Result<(Profile, CoreEvents), ProfileError> {
+        Profile::open_with(path, opts, None)
+    }
+
+    /// Like `open` but the profile is encrypted with `passphrase`. Unencrypted
+    /// profiles can be opened as well and are encrypted on the next save
+    pub fn open_encrypted(path: &Path, opts: ToxOptions, passphrase: &str)
+            -> Result<(Profile, CoreEvents), ProfileError> {
+        Profile::open_with(path, opts, Some(passphrase.to_string()))
+    }
+
+    fn open_with(path: &Path, opts: ToxOptions, passphrase: Option<String>)
+            -> Result<(Profile, CoreEvents), ProfileError> {
+        let lock = try!(lock(path));
+
+        let data = match File::open(path) {
+            Ok(mut file) => {
+                let mut data = Vec::new();
+                try!(file.read_to_end(&mut data));
+                Some(data)
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::FileNotFound => None,
+            Err(e) => return Err(ProfileError::Io(e)),
+        };
+
+        let (tox, events) = match ToxControl::new(opts) {
+            Some(t) => t,
+            None => return Err(ProfileError::Create),
+        };
+        match (data, &passphrase) {
+            (Some(data), &Some(ref pass)) => try!(tox.load_encrypted(data, pass)),
+            (Some(data), &None) => try!(tox.load(data)),
+            (None, _) => { },
+        }
+        let snapshot = try!(Snapshot::take(&tox));
+
+        let profile = Profile {
+            path: path.to_path_buf(),
+            tox: tox,
+            lock: lock,
+            passphrase: passphrase,
+            triggers: Vec::new(),
+            backups: 0,
+            last_save: unix_time(),
+            snapshot: snapshot,
+        };
+        Ok((profile, events))
+    }
+
+    /// Returns the tox instance of the profile
+    #[inline]
+    pub fn tox(&self) -> &ToxControl {
+        &self.tox
+    }
+
+    /// Returns the path of the profile
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Save the profile when `trigger` fires. See `poll`
+    pub fn autosave(&mut self, trigger: Trigger) {
+        if !self.triggers.contains(&trigger) {
+            self.triggers.push(trigger);
+        }
+    }
+
+    /// Keep the last `n` versions of the profile when it is overwritten
+    #[inline]
+    pub fn keep_backups(&mut self, n: usize) {
+        self.backups = n;
+    }
+
+    /// Encrypt the profile with `passphrase` from the next save on. `None` stores
+    /// it unencrypted
+    pub fn set_passphrase(&mut self, passphrase: Option<&str>) {
+        self.passphrase = passphrase.map(|p| p.to_string());
+    }
+
+    /// Check the triggers and save the profile if one of them has fired. Should be
+    /// called regularly, e.g., after every event. Returns `true` if the profile
+    /// has been saved
+    pub fn poll(&mut self) -> Result<bool, ProfileError> {
+        if self.triggers.len() == 0 {
+            return Ok(false);
+        }
+        let now = unix_time();
+        let snapshot = try!(Snapshot::take(&self.tox));
+        let fired = self.triggers.iter().any(|&t| match t {
+            Trigger::FriendList    => snapshot.friends != self.snapshot.friends,
+            Trigger::Name          => snapshot.name != self.snapshot.name,
+            Trigger::StatusMessage => snapshot.status != self.snapshot.status,
+            Trigger::Interval(n)   => now >= self.last_save + n,
+        });
+        if !fired {
+            return Ok(false);
+        }
+        try!(self.save());
+        self.snapshot = snapshot;
+        Ok(true)
+    }
+
+    /// Save the profile now
+    pub fn save(&mut self) -> Result<(), ProfileError> {
+        let data = match self.passphrase {
+            Some(ref pass) => try!(self.tox.save_encrypted(pass)),
+            None => try!(self.tox.save()),
+        };
+        try!(self.write(&data));
+        self.last_save = unix_time();
+        Ok(())
+    }
+
+    /// Save the profile and stop the tox instance
+    pub fn close(self) -> Result<(), ProfileError> {
+        let Profile { path, tox, lock, passphrase, backups, .. } = self;
+        let mut data = try!(tox.shutdown());
+        let data = match passphrase {
+            Some(ref pass) => {
+                let res = encryptsave::encrypt(&data, pass);
+                util::zero_memory(&mut data);
+                match res {
+                    Ok(data) => data,
+                    Err(_) => return Err(ProfileError::Save(SaveError::Encryption)),
+                }
+            },
+            None => data,
+        };
+        try!(write_atomic(&path, &data, backups));
+        drop(lock);
+        Ok(())
+    }
+
+    fn write(&self, data: &[u8]) -> io::Result<()> {
+        write_atomic(&self.path, data, self.backups)
+    }
+}
+
+/// Lock the profile at `path`. The lock is held until the returned file is closed.
+/// Fails with `Locked` if the profile is used by another process
+pub fn lock(path: &Path) -> Result<File, ProfileError> {
+    let lock = try!(OpenOptions::new().write(true).create(true)
+                                      .open(&with_suffix(path, ".lock")));
+    if unsafe { flock(lock.as_raw_fd(), LOCK_EX | LOCK_NB) } != 0 {
+        let err = io::Error::last_os_error();
+        return Err(match err.kind() {
+            io::ErrorKind::ResourceUnavailable => ProfileError::Locked,
+            _ => ProfileError::Io(err),
+        });
+    }
+    Ok(lock)
+}
+
+/// Replace the file at `path` with `data`. The previous version is kept as
+/// `<path>.1` and older ones as `<path>.2` up to `<path>.<backups>`. The new file
+/// is only readable by its owner since profiles contain the secret key
+pub fn write_atomic(path: &Path, data: &[u8], backups: usize) -> io::Result<()> {
+    let tmp = with_suffix(path, ".tmp");
+    // The mode only applies to new files
+    let _ = fs::remove_file(&tmp);
+    {
+        let mut file = try!(OpenOptions::new().write(true).create(true).truncate(true)
+                                              .mode(0o600).open(&tmp));
+        try!(file.write_all(data));
+        try!(file.sync_all());
+    }
+    if backups > 0 && fs::metadata(path).is_ok() {
+        for i in (1..backups).rev() {
+            let from = with_suffix(path, &format!(".{}", i));
+            if fs::metadata(&from).is_ok() {
+                try!(fs::rename(&from, &with_suffix(path, &format!(".{}", i + 1))));
+            }
+        }
+        try!(fs::copy(path, &with_suffix(path, ".1")));
+    }
+    try!(fs::rename(&tmp, path));
+    // Make the rename durable
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    try!(File::open(dir)).sync_all()
+}
@@ -0,0 +1,26 @@
+#![allow(dead_code)]
+
+use libc::{c_int, c_char};
+
+pub const QR_MODE_8:     c_int = 2;
+
+pub const QR_ECLEVEL_L:  c_int = 0;
+pub const QR_ECLEVEL_M:  c_int = 1;
+pub const QR_ECLEVEL_Q:  c_int = 2;
+pub const QR_ECLEVEL_H:  c_int = 3;
+
+#[repr(C)]
+#[allow(missing_copy_implementations)]
+pub struct QRcode {
+    pub version: c_int,
+    pub width: c_int,
+    /// `width * width` modules. The lowest bit is set for dark modules
+    pub data: *mut u8,
+}
+
+#[link(name = "qrencode")]
+extern {
+    pub fn QRcode_encodeString(string: *const c_char, version: c_int, level: c_int,
+                               hint: c_int, casesensitive: c_int) -> *mut QRcode;
+    pub fn QRcode_free(qrcode: *mut QRcode);
+}
@@ -0,0 +1,183 @@
+//! QR codes of tox addresses.
+//!
+//! The codes are generated with libqrencode and can be printed to a terminal or
+//! written as PNG images. This module is only built with the `qr` feature.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use tox::core::*;
+//! use tox::qr::{QrCode};
+//!
+//! let (tox, _events) = ToxControl::new(ToxOptions::new()).unwrap();
+//! let qr = QrCode::address(&tox.get_address().unwrap()).unwrap();
+//! println!("{}", qr.to_terminal());
+//! let png = qr.to_png(4);
+//! ```
+
+use std::{cmp, slice};
+
+use core::{Address};
+
+pub mod ll;
+
+/// Width of the light border around the code in modules
+const QUIET_ZONE: usize = 4;
+
+/// A QR code
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct QrCode {
+    width: usize,
+    /// `true` for dark modules, row by row
+    modules: Vec<bool>,
+}
+
+impl QrCode {
+    /// Encode `data` with medium error correction. Returns `None` if the data
+    /// contains a nul byte or is too long
+    pub fn encode(data: &str) -> Option<QrCode> {
+        if data.contains_char('\0') {
+            return None;
+        }
+        let mut s = data.as_bytes().to_vec();
+        s.push(0);
+        unsafe {
+            let code = ll::QRcode_encodeString(s.as_ptr() as *const _, 0,
+                                               ll::QR_ECLEVEL_M, ll::QR_MODE_8, 1);
+            if code.is_null() {
+                return None;
+            }
+            let width = (*code).width as usize;
+            let data = slice::from_raw_parts((*code).data, width * width);
+            let modules = data.iter().map(|&m| m & 1 == 1).collect();
+            ll::QRcode_free(code);
+            Some(QrCode { width: width, modules: modules })
+        }
+    }
+
+    /// Encode the `tox:` URI of the address. It is encoded in upper case so that
+    /// the smaller alphanumeric mode can be used
+    pub fn address(address: &Address) -> Option<QrCode> {
+        QrCode::encode(&format!("TOX:{}", address))
+    }
+
+    /// Returns the number of modules per side without the quiet zone
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns `true` if the module is dark. Modules outside of the code are light
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.width && self.modules[y * self.width + x]
+    }
+
+    /// Like `is_dark` but the coordinates include the quiet zone
+    fn is_dark_padded(&self, x: usize, y: usize) -> bool {
+        x >= QUIET_ZONE && y >= QUIET_ZONE && self.is_dark(x - QUIET_ZONE, y - QUIET_ZONE)
+    }
+
+    /// Render the code with Unicode block characters, two rows per line. Light
+    /// modules are drawn as blocks so that the code can be scanned from terminals
+    /// with a dark background
+    pub fn to_terminal(&self) -> String {
+        let size = self.width + 2 * QUIET_ZONE;
+        let mut out = String::new();
+        for y in range(0, (size + 1) / 2) {
+            for x in range(0, size) {
+                let top = !self.is_dark_padded(x, 2 * y);
+                let bottom = 2 * y + 1 < size && !self.is_dark_padded(x, 2 * y + 1);
+                out.push(match (top, bottom) {
+                    (true, true)   => '\u{2588}',
+                    (true, false)  => '\u{2580}',
+                    (false, true)  => '\u{2584}',
+                    (false, false) => ' ',
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Render the code as a grayscale PNG image with `scale` pixels per module. A
+    /// scale of 0 is treated as 1 since PNG images can't be empty
+    pub fn to_png(&self, scale: usize) -> Vec<u8> {
+        let scale = cmp::max(scale, 1);
+        let size = (self.width + 2 * QUIET_ZONE) * scale;
+        let mut raw = Vec::with_capacity((size + 1) * size);
+        for y in range(0, size) {
+            // Filter type none
+            raw.push(0);
+            for x in range(0, size) {
+                raw.push(match self.is_dark_padded(x / scale, y / scale) {
+                    true => 0,
+                    false => 255,
+                });
+            }
+        }
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut header = Vec::new();
+        push_u32(&mut header, size as u32);
+        push_u32(&mut header, size as u32);
+        // Bit depth 8, grayscale, deflate, no filtering, no interlacing
+        header.push_all(&[8, 0, 0, 0, 0]);
+        push_chunk(&mut png, b"IHDR", &header);
+        push_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        push_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn push_u32(out: &mut Vec<u8>, n: u32) {
+    out.push_all(&[(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data.iter() {
+        crc ^= b as u32;
+        for _ in range(0, 8) {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB88320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+fn push_chunk(out: &mut Vec<u8>, ty: &[u8], data: &[u8]) {
+    push_u32(out, data.len() as u32);
+    let start = out.len();
+    out.push_all(ty);
+    out.push_all(data);
+    let crc = crc32(&out[start..]);
+    push_u32(out, crc);
+}
+
+/// Wraps `data` in a zlib stream without compressing it. QR codes are small
+/// enough that this doesn't matter
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec!(0x78, 0x01);
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if data.len() == 0 {
+        out.push_all(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        out.push(match blocks.peek() {
+            Some(_) => 0,
+            None => 1,
+        });
+        out.push_all(&[len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8]);
+        out.push_all(block);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data.iter() {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    push_u32(&mut out, (b << 16) | a);
+    out
+}
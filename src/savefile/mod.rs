@@ -0,0 +1,695 @@
+//! Reading and writing the save data format without toxcore.
+//!
+//! The data returned by `ToxControl::save` consists of a header and a list of
+//! sections. `SaveFile::parse` turns it into plain structs which can be inspected
+//! and modified. `SaveFile::to_bytes` writes them back in the order used by
+//! toxcore, so data that has been parsed and written again is identical to the
+//! input unless it contains sections in an unusual order.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use tox::core::*;
+//! use tox::savefile::{SaveFile};
+//!
+//! let (tox, _events) = ToxControl::new(ToxOptions::new()).unwrap();
+//! let data = tox.save().unwrap();
+//! let mut save = SaveFile::parse(&data).unwrap();
+//! assert_eq!(save.to_bytes(), data);
+//! println!("{}", save.address());
+//! for friend in save.friends.iter() {
+//!     println!("{} {} {}", friend.public_key, friend.name, friend.last_seen);
+//! }
+//! save.name = ToxString::from("renamed".to_string());
+//! tox.load(save.to_bytes()).unwrap();
+//! ```
+
+use std::{cmp, error, fmt};
+
+use core::{Address, ClientId, PublicKey, SecretKey, UserStatus, ToxString, ID_CLIENT_SIZE,
+           SECRET_KEY_SIZE, MAX_NAME_LENGTH, MAX_STATUSMESSAGE_LENGTH};
+use encryptsave;
+
+const STATE_COOKIE_GLOBAL: u32 = 0x15ed1b1f;
+const STATE_COOKIE_TYPE:   u16 = 0x01ce;
+
+const STATE_TYPE_NOSPAMKEYS:    u16 = 1;
+const STATE_TYPE_DHT:           u16 = 2;
+const STATE_TYPE_FRIENDS:       u16 = 3;
+const STATE_TYPE_NAME:          u16 = 4;
+const STATE_TYPE_STATUSMESSAGE: u16 = 5;
+const STATE_TYPE_STATUS:        u16 = 6;
+const STATE_TYPE_TCP_RELAY:     u16 = 10;
+const STATE_TYPE_PATH_NODE:     u16 = 11;
+
+const DHT_STATE_COOKIE_GLOBAL: u32 = 0x0159000d;
+const DHT_STATE_COOKIE_TYPE:   u16 = 0x11ce;
+const DHT_STATE_TYPE_NODES:    u16 = 4;
+
+/// Size of the friend request message buffer of a saved friend
+const FRIEND_REQUEST_SIZE: usize = 1024;
+/// Size of a saved friend including the padding of the C struct
+const FRIEND_SIZE: usize = 2216;
+
+const AF_INET:      u8 = 2;
+const AF_INET6:     u8 = 10;
+const TCP_INET:     u8 = 130;
+const TCP_INET6:    u8 = 138;
+
+/// Errors of `SaveFile::parse`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FormatError {
+    /// The data is encrypted and has to be decrypted first
+    Encrypted,
+    /// The header is missing or invalid
+    BadMagic,
+    /// The data ends in the middle of a section
+    Truncated,
+    /// The section with the type is malformed
+    InvalidSection(u16),
+    /// The data doesn't contain the keys
+    MissingKeys,
+}
+
+impl error::Error for FormatError {
+    fn description(&self) -> &str {
+        match *self {
+            FormatError::Encrypted         => "save data is encrypted",
+            FormatError::BadMagic          => "not a tox save file",
+            FormatError::Truncated         => "save data is truncated",
+            FormatError::InvalidSection(_) => "malformed section",
+            FormatError::MissingKeys       => "save data contains no keys",
+        }
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FormatError::InvalidSection(ty) => write!(fmt, "malformed section {}", ty),
+            _ => fmt.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+/// IP address of a saved node
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum NodeIp {
+    V4([u8; 4]),
+    V6([u8; 16]),
+}
+
+impl fmt::Display for NodeIp {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NodeIp::V4(ip) => write!(fmt, "{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]),
+            NodeIp::V6(ip) => {
+                for i in range(0, 8) {
+                    if i > 0 {
+                        try!(fmt.write_str(":"));
+                    }
+                    try!(write!(fmt, "{:x}", (ip[2*i] as u16) << 8 | ip[2*i+1] as u16));
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// A DHT node, TCP relay or onion path node
+#[derive(Clone, PartialEq, Debug)]
+pub struct PackedNode {
+    /// `true` if the node is reached via TCP
+    pub tcp: bool,
+    pub ip: NodeIp,
+    pub port: u16,
+    pub public_key: PublicKey,
+}
+
+/// The state of a friend as stored by toxcore
+#[repr(u8)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FriendStatus {
+    /// Added with a friend request that has not been sent yet
+    Added = 1,
+    /// A friend request has been sent but not accepted yet
+    Requested = 2,
+    /// The friend has accepted our request or was added without one
+    Confirmed = 3,
+    Online = 4,
+}
+
+/// A saved friend
+#[derive(Clone, Debug)]
+pub struct Friend {
+    pub status: FriendStatus,
+    pub public_key: PublicKey,
+    /// The message of our friend request. toxcore sends it again after loading
+    /// while `status` is `Added` or `Requested`
+    pub request_message: ToxString,
+    pub name: ToxString,
+    pub status_message: ToxString,
+    pub user_status: UserStatus,
+    /// The nospam of the address we sent the friend request to
+    pub request_nospam: [u8; 4],
+    /// When the friend was last seen online in seconds since the epoch. 0 if
+    /// never
+    pub last_seen: u64,
+}
+
+/// The contents of tox save data
+#[derive(Debug)]
+pub struct SaveFile {
+    pub nospam: [u8; 4],
+    pub public_key: PublicKey,
+    pub secret_key: SecretKey,
+    pub dht_nodes: Vec<PackedNode>,
+    pub friends: Vec<Friend>,
+    pub name: ToxString,
+    pub status_message: ToxString,
+    pub status: UserStatus,
+    pub tcp_relays: Vec<PackedNode>,
+    pub path_nodes: Vec<PackedNode>,
+    /// Sections of the DHT state this parser doesn't know, `(type, data)`
+    pub dht_unknown: Vec<(u16, Vec<u8>)>,
+    /// Sections this parser doesn't know, `(type, data)`
+    pub unknown: Vec<(u16, Vec<u8>)>,
+}
+
+fn read_u16_le(data: &[u8]) -> u16 {
+    data[0] as u16 | (data[1] as u16) << 8
+}
+
+fn read_u32_le(data: &[u8]) -> u32 {
+    data.iter().take(4).rev().fold(0, |n, &b| n << 8 | b as u32)
+}
+
+fn read_u16_be(data: &[u8]) -> u16 {
+    (data[0] as u16) << 8 | data[1] as u16
+}
+
+fn read_u64_be(data: &[u8]) -> u64 {
+    data.iter().take(8).fold(0, |n, &b| n << 8 | b as u64)
+}
+
+fn write_u32_le(out: &mut Vec<u8>, n: u32) {
+    for i in range(0, 4) {
+        out.push((n >> (8 * i)) as u8);
+    }
+}
+
+fn write_u16_be(out: &mut Vec<u8>, n: u16) {
+    out.push((n >> 8) as u8);
+    out.push(n as u8);
+}
+
+fn write_u64_be(out: &mut Vec<u8>, n: u64) {
+    for i in range(0, 8).rev() {
+        out.push((n >> (8 * i)) as u8);
+    }
+}
+
+fn public_key(data: &[u8]) -> PublicKey {
+    let mut raw = [0u8; ID_CLIENT_SIZE];
+    for (dst, &src) in raw.iter_mut().zip(data.iter()) {
+        *dst = src;
+    }
+    ClientId { raw: raw }
+}
+
+fn user_status(n: u8) -> Option<UserStatus> {
+    match n {
+        0 => Some(UserStatus::None),
+        1 => Some(UserStatus::Away),
+        2 => Some(UserStatus::Busy),
+        _ => None,
+    }
+}
+
+/// Splits `data` into `(type, contents)` sections
+fn sections(mut data: &[u8], cookie: u16) -> Result<Vec<(u16, &[u8])>, FormatError> {
+    let mut sections = Vec::new();
+    while data.len() > 0 {
+        if data.len() < 8 {
+            return Err(FormatError::Truncated);
+        }
+        let len = read_u32_le(data) as usize;
+        let ty = read_u16_le(&data[4..]);
+        if read_u16_le(&data[6..]) != cookie {
+            return Err(FormatError::InvalidSection(ty));
+        }
+        if data.len() - 8 < len {
+            return Err(FormatError::Truncated);
+        }
+        sections.push((ty, &data[8..8+len]));
+        data = &data[8+len..];
+    }
+    Ok(sections)
+}
+
+fn write_section(out: &mut Vec<u8>, ty: u16, cookie: u16, data: &[u8]) {
+    write_u32_le(out, data.len() as u32);
+    write_u32_le(out, ty as u32 | (cookie as u32) << 16);
+    out.push_all(data);
+}
+
+fn parse_nodes(mut data: &[u8], ty: u16) -> Result<Vec<PackedNode>, FormatError> {
+    let mut nodes = Vec::new();
+    while data.len() > 0 {
+        let (tcp, ip_len) = match data[0] {
+            AF_INET   => (false, 4),
+            AF_INET6  => (false, 16),
+            TCP_INET  => (true, 4),
+            TCP_INET6 => (true, 16),
+            _ => return Err(FormatError::InvalidSection(ty)),
+        };
+        let size = 1 + ip_len + 2 + ID_CLIENT_SIZE;
+        if data.len() < size {
+            return Err(FormatError::InvalidSection(ty));
+        }
+        let ip = match ip_len {
+            4 => {
+                let mut ip = [0u8; 4];
+                for (dst, &src) in ip.iter_mut().zip(data[1..].iter()) { *dst = src; }
+                NodeIp::V4(ip)
+            },
+            _ => {
+                let mut ip = [0u8; 16];
+                for (dst, &src) in ip.iter_mut().zip(data[1..].iter()) { *dst = src; }
+                NodeIp::V6(ip)
+            },
+        };
+        nodes.push(PackedNode {
+            tcp: tcp,
+            ip: ip,
+            port: read_u16_be(&data[1+ip_len..]),
+            public_key: public_key(&data[3+ip_len..]),
+        });
+        data = &data[size..];
+    }
+    Ok(nodes)
+}
+
+fn write_nodes(out: &mut Vec<u8>, nodes: &[PackedNode]) {
+    for node in nodes.iter() {
+        match (node.tcp, node.ip) {
+            (false, NodeIp::V4(ip)) => { out.push(AF_INET);   out.push_all(&ip); },
+            (false, NodeIp::V6(ip)) => { out.push(AF_INET6);  out.push_all(&ip); },
+            (true,  NodeIp::V4(ip)) => { out.push(TCP_INET);  out.push_all(&ip); },
+            (true,  NodeIp::V6(ip)) => { out.push(TCP_INET6); out.push_all(&ip); },
+        }
+        write_u16_be(out, node.port);
+        out.push_all(&node.public_key.raw);
+    }
+}
+
+/// Reads a string that is stored in a fixed size buffer with a big endian length
+fn fixed_string(buf: &[u8], len: &[u8]) -> Option<ToxString> {
+    let len = read_u16_be(len) as usize;
+    match len <= buf.len() {
+        true => Some(ToxString::from_bytes(buf[..len].to_vec())),
+        false => None,
+    }
+}
+
+fn write_fixed_string(out: &mut Vec<u8>, s: &ToxString, size: usize) {
+    let bytes = &s.as_bytes()[..cmp::min(s.as_bytes().len(), size)];
+    out.push_all(bytes);
+    for _ in range(bytes.len(), size) {
+        out.push(0);
+    }
+}
+
+fn parse_friend(data: &[u8]) -> Option<Friend> {
+    let status = match data[0] {
+        1 => FriendStatus::Added,
+        2 => FriendStatus::Requested,
+        3 => FriendStatus::Confirmed,
+        4 => FriendStatus::Online,
+        _ => return None,
+    };
+    let request_message = match fixed_string(&data[33..1057], &data[1058..]) {
+        Some(s) => s,
+        None => return None,
+    };
+    let name = match fixed_string(&data[1060..1188], &data[1188..]) {
+        Some(s) => s,
+        None => return None,
+    };
+    let status_message = match fixed_string(&data[1190..2197], &data[2198..]) {
+        Some(s) => s,
+        None => return None,
+    };
+    let user_status = match user_status(data[2200]) {
+        Some(s) => s,
+        None => return None,
+    };
+    let mut request_nospam = [0u8; 4];
+    for (dst, &src) in request_nospam.iter_mut().zip(data[2204..].iter()) {
+        *dst = src;
+    }
+    Some(Friend {
+        status: status,
+        public_key: public_key(&data[1..]),
+        request_message: request_message,
+        name: name,
+        status_message: status_message,
+        user_status: user_status,
+        request_nospam: request_nospam,
+        last_seen: read_u64_be(&data[2208..]),
+    })
+}
+
+/// Writes the friend in the layout of the C struct. The padding is zeroed
+fn write_friend(out: &mut Vec<u8>, friend: &Friend) {
+    let len = |s: &ToxString, max: usize| cmp::min(s.as_bytes().len(), max) as u16;
+    out.push(friend.status as u8);
+    out.push_all(&friend.public_key.raw);
+    write_fixed_string(out, &friend.request_message, FRIEND_REQUEST_SIZE);
+    out.push(0);
+    write_u16_be(out, len(&friend.request_message, FRIEND_REQUEST_SIZE));
+    write_fixed_string(out, &friend.name, MAX_NAME_LENGTH);
+    write_u16_be(out, len(&friend.name, MAX_NAME_LENGTH));
+    write_fixed_string(out, &friend.status_message, MAX_STATUSMESSAGE_LENGTH);
+    out.push(0);
+    write_u16_be(out, len(&friend.status_message, MAX_STATUSMESSAGE_LENGTH));
+    out.push(friend.user_status as u8);
+    out.push_all(&[0, 0, 0]);
+    out.push_all(&friend.request_nospam);
+    write_u64_be(out, friend.last_seen);
+}
+
+impl SaveFile {
+    /// Parse save data
+    pub fn parse(data: &[u8]) -> Result<SaveFile, FormatError> {
+        if encryptsave::is_encrypted(data) {
+            return Err(FormatError::Encrypted);
+        }
+        if data.len() < 8 || read_u32_le(data) != 0
+                || read_u32_le(&data[4..]) != STATE_COOKIE_GLOBAL {
+            return Err(FormatError::BadMagic);
+        }
+
+        let mut keys = None;
+        let mut save = SaveFile {
+            nospam: [0; 4],
+            public_key: ClientId { raw: [0; ID_CLIENT_SIZE] },
+            secret_key: SecretKey::new([0; SECRET_KEY_SIZE]),
+            dht_nodes: Vec::new(),
+            friends: Vec::new(),
+            name: ToxString::from_bytes(Vec::new()),
+            status_message: ToxString::from_bytes(Vec::new()),
+            status: UserStatus::None,
+            tcp_relays: Vec::new(),
+            path_nodes: Vec::new(),
+            dht_unknown: Vec::new(),
+            unknown: Vec::new(),
+        };
+        for (ty, section) in try!(sections(&data[8..], STATE_COOKIE_TYPE)).into_iter() {
+            match ty {
+                STATE_TYPE_NOSPAMKEYS => {
+                    if section.len() != 4 + ID_CLIENT_SIZE + SECRET_KEY_SIZE {
+                        return Err(FormatError::InvalidSection(ty));
+                    }
+                    keys = Some(section);
+                },
+                STATE_TYPE_DHT => try!(save.parse_dht(section)),
+                STATE_TYPE_FRIENDS => {
+                    if section.len() % FRIEND_SIZE != 0 {
+                        return Err(FormatError::InvalidSection(ty));
+                    }
+                    for friend in section.chunks(FRIEND_SIZE) {
+                        match parse_friend(friend) {
+                            Some(friend) => save.friends.push(friend),
+                            None => return Err(FormatError::InvalidSection(ty)),
+                        }
+                    }
+                },
+                STATE_TYPE_NAME => save.name = ToxString::from_bytes(section.to_vec()),
+                STATE_TYPE_STATUSMESSAGE =>
+                    save.status_message = ToxString::from_bytes(section.to_vec()),
+                STATE_TYPE_STATUS => {
+                    save.status = match section.first().and_then(|&s| user_status(s)) {
+                        Some(s) if section.len() == 1 => s,
+                        _ => return Err(FormatError::InvalidSection(ty)),
+                    };
+                },
+                STATE_TYPE_TCP_RELAY => save.tcp_relays = try!(parse_nodes(section, ty)),
+                STATE_TYPE_PATH_NODE => save.path_nodes = try!(parse_nodes(section, ty)),
+                _ => save.unknown.push((ty, section.to_vec())),
+            }
+        }
+
+        let keys = match keys {
+            Some(keys) => keys,
+            None => return Err(FormatError::MissingKeys),
+        };
+        for (dst, &src) in save.nospam.iter_mut().zip(keys.iter()) {
+            *dst = src;
+        }
+        save.public_key = public_key(&keys[4..]);
+        let mut secret = [0u8; SECRET_KEY_SIZE];
+        for (dst, &src) in secret.iter_mut().zip(keys[4+ID_CLIENT_SIZE..].iter()) {
+            *dst = src;
+        }
+        save.secret_key = SecretKey::new(secret);
+        Ok(save)
+    }
+
+    fn parse_dht(&mut self, data: &[u8]) -> Result<(), FormatError> {
+        if data.len() < 4 || read_u32_le(data) != DHT_STATE_COOKIE_GLOBAL {
+            return Err(FormatError::InvalidSection(STATE_TYPE_DHT));
+        }
+        let sections = match sections(&data[4..], DHT_STATE_COOKIE_TYPE) {
+            Ok(s) => s,
+            Err(_) => return Err(FormatError::InvalidSection(STATE_TYPE_DHT)),
+        };
+        for (ty, section) in sections.into_iter() {
+            match ty {
+                DHT_STATE_TYPE_NODES =>
+                    self.dht_nodes = try!(parse_nodes(section, STATE_TYPE_DHT)),
+                _ => self.dht_unknown.push((ty, section.to_vec())),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns our address
+    pub fn address(&self) -> Address {
+        Address::new(&self.public_key, self.nospam)
+    }
+
+    /// Write the save data
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_u32_le(&mut out, 0);
+        write_u32_le(&mut out, STATE_COOKIE_GLOBAL);
+
+        let mut keys = Vec::new();
+        keys.push_all(&self.nospam);
+        keys.push_all(&self.public_key.raw);
+        keys.push_all(self.secret_key.as_bytes());
+        write_section(&mut out, STATE_TYPE_NOSPAMKEYS, STATE_COOKIE_TYPE, &keys);
+
+        let mut dht = Vec::new();
+        write_u32_le(&mut dht, DHT_STATE_COOKIE_GLOBAL);
+        let mut nodes = Vec::new();
+        write_nodes(&mut nodes, &self.dht_nodes);
+        write_section(&mut dht, DHT_STATE_TYPE_NODES, DHT_STATE_COOKIE_TYPE, &nodes);
+        for &(ty, ref data) in self.dht_unknown.iter() {
+            write_section(&mut dht, ty, DHT_STATE_COOKIE_TYPE, data);
+        }
+        write_section(&mut out, STATE_TYPE_DHT, STATE_COOKIE_TYPE, &dht);
+
+        let mut friends = Vec::new();
+        for friend in self.friends.iter() {
+            write_friend(&mut friends, friend);
+        }
+        write_section(&mut out, STATE_TYPE_FRIENDS, STATE_COOKIE_TYPE, &friends);
+
+        write_section(&mut out, STATE_TYPE_NAME, STATE_COOKIE_TYPE, self.name.as_bytes());
+        write_section(&mut out, STATE_TYPE_STATUSMESSAGE, STATE_COOKIE_TYPE,
+                      self.status_message.as_bytes());
+        write_section(&mut out, STATE_TYPE_STATUS, STATE_COOKIE_TYPE,
+                      &[self.status as u8]);
+
+        let mut relays = Vec::new();
+        write_nodes(&mut relays, &self.tcp_relays);
+        write_section(&mut out, STATE_TYPE_TCP_RELAY, STATE_COOKIE_TYPE, &relays);
+
+        let mut path = Vec::new();
+        write_nodes(&mut path, &self.path_nodes);
+        write_section(&mut out, STATE_TYPE_PATH_NODE, STATE_COOKIE_TYPE, &path);
+
+        for &(ty, ref data) in self.unknown.iter() {
+            write_section(&mut out, ty, STATE_COOKIE_TYPE, data);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::old_io::{timer};
+    use std::time::{Duration};
+
+    use core::{ToxControl, ToxOptions, ClientId, UserStatus, ToxString};
+    use super::{SaveFile, FormatError, FriendStatus, NodeIp};
+
+    /// Save data with one friend, one node of each kind and the end section
+    /// written by newer versions of toxcore. Built by hand, the `toxcore_*` tests
+    /// use data written by toxcore
+    static DATA: &'static [u8] = include_bytes!("test.tox");
+
+    fn s(s: &str) -> ToxString {
+        ToxString::from(s.to_string())
+    }
+
+    #[test]
+    fn round_trip() {
+        let save = SaveFile::parse(DATA).unwrap();
+        assert_eq!(save.to_bytes(), DATA);
+        let again = SaveFile::parse(&save.to_bytes()).unwrap();
+        assert_eq!(again.to_bytes(), DATA);
+    }
+
+    #[test]
+    fn fields() {
+        let save = SaveFile::parse(DATA).unwrap();
+        assert_eq!(save.nospam, [1, 2, 3, 4]);
+        assert_eq!(save.public_key.raw.to_vec(), range(0x10u8, 0x30).collect::<Vec<_>>());
+        assert_eq!(save.secret_key.as_bytes().to_vec(),
+                   range(0x40u8, 0x60).collect::<Vec<_>>());
+        assert_eq!(save.name, s("Tox User"));
+        assert_eq!(save.status_message, s("Toxing on Rust"));
+        assert_eq!(save.status, UserStatus::Busy);
+
+        assert_eq!(save.dht_nodes.len(), 1);
+        assert!(!save.dht_nodes[0].tcp);
+        assert_eq!(save.dht_nodes[0].ip, NodeIp::V4([127, 0, 0, 1]));
+        assert_eq!(save.dht_nodes[0].port, 33445);
+        assert_eq!(save.dht_nodes[0].public_key.raw, [0xAA; 32]);
+        assert_eq!(save.tcp_relays.len(), 1);
+        assert!(save.tcp_relays[0].tcp);
+        assert_eq!(format!("{}", save.tcp_relays[0].ip), "0:0:0:0:0:0:0:1");
+        assert_eq!(save.tcp_relays[0].port, 443);
+        assert_eq!(save.path_nodes.len(), 1);
+        assert_eq!(save.path_nodes[0].ip, NodeIp::V4([10, 0, 0, 1]));
+
+        assert_eq!(save.friends.len(), 1);
+        let friend = &save.friends[0];
+        assert_eq!(friend.status, FriendStatus::Confirmed);
+        assert_eq!(friend.public_key.raw.to_vec(),
+                   range(0x60u8, 0x80).collect::<Vec<_>>());
+        assert_eq!(friend.request_message, s(""));
+        assert_eq!(friend.name, s("Alice"));
+        assert_eq!(friend.status_message, s("Hello"));
+        assert_eq!(friend.user_status, UserStatus::Away);
+        assert_eq!(friend.request_nospam, [5, 6, 7, 8]);
+        assert_eq!(friend.last_seen, 1420070400);
+
+        assert!(save.dht_unknown.is_empty());
+        assert_eq!(save.unknown, vec!((255, vec!())));
+    }
+
+    /// Checks that data written by `tox` round-trips and returns it parsed
+    fn toxcore_save(tox: &ToxControl) -> SaveFile {
+        let data = tox.save().unwrap();
+        let save = SaveFile::parse(&data).unwrap();
+        assert_eq!(save.to_bytes(), data);
+        assert_eq!(save.address(), tox.get_address().unwrap());
+        save
+    }
+
+    #[test]
+    fn toxcore_round_trip() {
+        let (tox, _events) = ToxControl::new(ToxOptions::new()).unwrap();
+        let (requested, _events2) = ToxControl::new(ToxOptions::new()).unwrap();
+        let (added, _events3) = ToxControl::new(ToxOptions::new()).unwrap();
+        tox.set_name("Tox User".to_string()).unwrap();
+        tox.set_status_message("Toxing on Rust".to_string()).unwrap();
+        tox.set_user_status(UserStatus::Away).unwrap();
+        let address = requested.get_address().unwrap();
+        tox.add_friend(Box::new(address.clone()), "Hi".to_string()).unwrap();
+        let key = added.get_address().unwrap().client_id().clone();
+        tox.add_friend_norequest(Box::new(key.clone())).unwrap();
+
+        let save = toxcore_save(&tox);
+        assert_eq!(save.name, s("Tox User"));
+        assert_eq!(save.status_message, s("Toxing on Rust"));
+        assert_eq!(save.status, UserStatus::Away);
+        assert_eq!(save.friends.len(), 2);
+        let friend = &save.friends[0];
+        // The request can already have been sent via LAN discovery
+        assert!(friend.status == FriendStatus::Added
+                || friend.status == FriendStatus::Requested);
+        assert_eq!(&friend.public_key, address.client_id());
+        assert_eq!(friend.request_message, s("Hi"));
+        assert_eq!(friend.request_nospam, address.nospam());
+        assert_eq!(save.friends[1].status, FriendStatus::Confirmed);
+        assert_eq!(save.friends[1].public_key, key);
+
+        // Edited data is accepted by toxcore
+        let mut save = save;
+        save.name = s("renamed");
+        tox.load(save.to_bytes()).unwrap();
+        assert_eq!(tox.get_self_name().unwrap(), s("renamed"));
+    }
+
+    /// Needs a network connection. TCP relays and onion path nodes are only saved
+    /// once toxcore is connected
+    #[test]
+    #[ignore]
+    fn toxcore_round_trip_online() {
+        let (tox, _events) = ToxControl::new(ToxOptions::new()).unwrap();
+        let key = "951C88B7E75C867418ACDB5D273821372BB5BD652740BCDF623A4FA293E75D2F"
+                  .parse::<ClientId>().unwrap();
+        let host = "192.254.75.98".to_string();
+        tox.bootstrap_from_address(host.clone(), 33445, Box::new(key.clone())).unwrap();
+        tox.add_tcp_relay(host, 443, Box::new(key)).unwrap();
+        for _ in range(0, 120) {
+            let save = toxcore_save(&tox);
+            if save.dht_nodes.len() > 0 && save.tcp_relays.len() > 0
+                    && save.path_nodes.len() > 0 {
+                return;
+            }
+            timer::sleep(Duration::seconds(1));
+        }
+        panic!("not connected after two minutes");
+    }
+
+    #[test]
+    fn truncated() {
+        // Must not panic. Cuts at section boundaries can be valid
+        for len in range(0, DATA.len()) {
+            let _ = SaveFile::parse(&DATA[..len]);
+        }
+        assert_eq!(SaveFile::parse(&DATA[..4]).unwrap_err(), FormatError::BadMagic);
+        assert_eq!(SaveFile::parse(&DATA[..8]).unwrap_err(), FormatError::MissingKeys);
+        assert_eq!(SaveFile::parse(&DATA[..50]).unwrap_err(), FormatError::Truncated);
+        assert_eq!(SaveFile::parse(&DATA[..DATA.len()-1]).unwrap_err(),
+                   FormatError::Truncated);
+    }
+
+    #[test]
+    fn malformed() {
+        let broken = |pos: usize, byte: u8| {
+            let mut data = DATA.to_vec();
+            data[pos] = byte;
+            SaveFile::parse(&data).unwrap_err()
+        };
+        // Section cookie of the keys
+        assert_eq!(broken(14, 0), FormatError::InvalidSection(1));
+        // Global cookie of the DHT state
+        assert_eq!(broken(92, 0), FormatError::InvalidSection(2));
+        // Family of the DHT node
+        assert_eq!(broken(104, 3), FormatError::InvalidSection(2));
+        // Status of the friend
+        assert_eq!(broken(151, 9), FormatError::InvalidSection(3));
+        // Length of the friend's name
+        assert_eq!(broken(151 + 1188, 1), FormatError::InvalidSection(3));
+        // User status
+        assert_eq!(broken(2413, 3), FormatError::InvalidSection(6));
+        assert_eq!(broken(0, 1), FormatError::BadMagic);
+    }
+}
@@ -1,4 +1,4 @@
-#![feature(plugin, collections, std_misc, libc, old_io, path, core)]
+#![feature(plugin, collections, std_misc, libc, old_io, path, core, io, fs)]
 #![crate_type = "lib"]
 #![crate_name = "tox"]
 #![allow(non_camel_case_types)]
@@ -10,3 +10,10 @@ extern crate comm;
 pub mod core;
 pub mod av;
 pub mod util;
+pub mod future;
+pub mod bootstrap;
+pub mod encryptsave;
+pub mod profile;
+pub mod savefile;
+#[cfg(feature = "qr")]
+pub mod qr;
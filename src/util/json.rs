@@ -0,0 +1,374 @@
+//! A minimal JSON reader and writer.
+
+use std::{char, fmt, str};
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Returns the value of the member `key` if this is an object
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref members) => {
+                members.iter().find(|&&(ref k, _)| k == key).map(|&(_, ref v)| v)
+            },
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match *self {
+            Json::Array(ref a) => Some(a),
+            _ => None,
+        }
+    }
+}
+
+fn write_string(fmt: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    try!(fmt.write_str("\""));
+    for c in s.chars() {
+        match c {
+            '"' => try!(fmt.write_str("\\\"")),
+            '\\' => try!(fmt.write_str("\\\\")),
+            '\n' => try!(fmt.write_str("\\n")),
+            '\r' => try!(fmt.write_str("\\r")),
+            '\t' => try!(fmt.write_str("\\t")),
+            c if (c as u32) < 0x20 => try!(write!(fmt, "\\u{:04x}", c as u32)),
+            c => try!(write!(fmt, "{}", c)),
+        }
+    }
+    fmt.write_str("\"")
+}
+
+/// Writes the value as compact JSON
+impl fmt::Display for Json {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => fmt.write_str("null"),
+            Json::Bool(b) => write!(fmt, "{}", b),
+            Json::Number(n) => write!(fmt, "{}", n),
+            Json::String(ref s) => write_string(fmt, s),
+            Json::Array(ref values) => {
+                try!(fmt.write_str("["));
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        try!(fmt.write_str(","));
+                    }
+                    try!(write!(fmt, "{}", v));
+                }
+                fmt.write_str("]")
+            },
+            Json::Object(ref members) => {
+                try!(fmt.write_str("{"));
+                for (i, &(ref k, ref v)) in members.iter().enumerate() {
+                    if i > 0 {
+                        try!(fmt.write_str(","));
+                    }
+                    try!(write_string(fmt, k));
+                    try!(write!(fmt, ":{}", v));
+                }
+                fmt.write_str("}")
+            },
+        }
+    }
+}
+
+/// How deeply arrays and objects can be nested. Deeper documents are rejected
+/// instead of overflowing the stack
+const MAX_DEPTH: usize = 128;
+
+/// Parses a JSON document. Returns `None` if it's not valid JSON or nested more
+/// than 128 levels deep
+pub fn parse(src: &str) -> Option<Json> {
+    let mut parser = Parser { src: src.as_bytes(), pos: 0, depth: 0 };
+    let value = match parser.value() {
+        Some(v) => v,
+        None => return None,
+    };
+    parser.whitespace();
+    match parser.pos == parser.src.len() {
+        true => Some(value),
+        false => None,
+    }
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+    /// The number of arrays and objects the parser is in
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).map(|&b| b)
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let b = self.peek();
+        if b.is_some() {
+            self.pos += 1;
+        }
+        b
+    }
+
+    fn whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn literal(&mut self, lit: &[u8], value: Json) -> Option<Json> {
+        if self.src[self.pos..].starts_with(lit) {
+            self.pos += lit.len();
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.whitespace();
+        match self.peek() {
+            Some(b'n') => self.literal(b"null", Json::Null),
+            Some(b't') => self.literal(b"true", Json::Bool(true)),
+            Some(b'f') => self.literal(b"false", Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') | Some(b'{') => {
+                if self.depth == MAX_DEPTH {
+                    return None;
+                }
+                self.depth += 1;
+                let value = match self.peek() {
+                    Some(b'[') => self.array(),
+                    _ => self.object(),
+                };
+                self.depth -= 1;
+                value
+            },
+            Some(b'-') | Some(b'0'...b'9') => self.number(),
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.pos;
+        while let Some(b'0'...b'9') | Some(b'-') | Some(b'+') | Some(b'.') | Some(b'e')
+                | Some(b'E') = self.peek() {
+            self.pos += 1;
+        }
+        let s = str::from_utf8(&self.src[start..self.pos]).unwrap();
+        s.parse().ok().map(Json::Number)
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let mut n = 0;
+        for _ in range(0, 4) {
+            let d = match self.next() {
+                Some(b @ b'0'...b'9') => b - b'0',
+                Some(b @ b'a'...b'f') => b - b'a' + 10,
+                Some(b @ b'A'...b'F') => b - b'A' + 10,
+                _ => return None,
+            };
+            n = n * 16 + d as u32;
+        }
+        Some(n)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.pos += 1;
+        let mut buf = Vec::new();
+        loop {
+            match self.next() {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    let c = match self.next() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\x08',
+                        Some(b'f') => '\x0c',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let mut n = match self.hex4() {
+                                Some(n) => n,
+                                None => return None,
+                            };
+                            // Surrogate pair
+                            if n >= 0xD800 && n < 0xDC00 {
+                                if !self.src[self.pos..].starts_with(b"\\u") {
+                                    return None;
+                                }
+                                self.pos += 2;
+                                let low = match self.hex4() {
+                                    Some(low) if low >= 0xDC00 && low < 0xE000 => low,
+                                    _ => return None,
+                                };
+                                n = 0x10000 + ((n - 0xD800) << 10) + (low - 0xDC00);
+                            }
+                            match char::from_u32(n) {
+                                Some(c) => c,
+                                None => return None,
+                            }
+                        },
+                        _ => return None,
+                    };
+                    let mut tmp = String::new();
+                    tmp.push(c);
+                    buf.push_all(tmp.as_bytes());
+                },
+                Some(b) => buf.push(b),
+                None => return None,
+            }
+        }
+        String::from_utf8(buf).ok()
+    }
+
+    fn array(&mut self) -> Option<Json> {
+        self.pos += 1;
+        let mut values = Vec::new();
+        self.whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Some(Json::Array(values));
+        }
+        loop {
+            match self.value() {
+                Some(v) => values.push(v),
+                None => return None,
+            }
+            self.whitespace();
+            match self.next() {
+                Some(b',') => { },
+                Some(b']') => return Some(Json::Array(values)),
+                _ => return None,
+            }
+        }
+    }
+
+    fn object(&mut self) -> Option<Json> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Some(Json::Object(members));
+        }
+        loop {
+            self.whitespace();
+            if self.peek() != Some(b'"') {
+                return None;
+            }
+            let key = match self.string() {
+                Some(k) => k,
+                None => return None,
+            };
+            self.whitespace();
+            if self.next() != Some(b':') {
+                return None;
+            }
+            match self.value() {
+                Some(v) => members.push((key, v)),
+                None => return None,
+            }
+            self.whitespace();
+            match self.next() {
+                Some(b',') => { },
+                Some(b'}') => return Some(Json::Object(members)),
+                _ => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, Json};
+
+    #[test]
+    fn values() {
+        let doc = parse(r#" {"a": [1, -2.5e1, true, false, null], "b": {}, "c": []} "#);
+        assert_eq!(doc, Some(Json::Object(vec!(
+            ("a".to_string(), Json::Array(vec!(Json::Number(1.0), Json::Number(-25.0),
+                                               Json::Bool(true), Json::Bool(false),
+                                               Json::Null))),
+            ("b".to_string(), Json::Object(vec!())),
+            ("c".to_string(), Json::Array(vec!())),
+        ))));
+    }
+
+    #[test]
+    fn escapes() {
+        let s = |src: &str| parse(src).and_then(|v| v.as_str().map(|s| s.to_string()));
+        assert_eq!(s(r#""a\"\\\/\b\f\n\r\t""#).unwrap(), "a\"\\/\x08\x0c\n\r\t");
+        assert_eq!(s(r#""\u00e4\u20AC""#).unwrap(), "\u{e4}\u{20ac}");
+        assert_eq!(s(r#""\ud83d\ude00""#).unwrap(), "\u{1f600}");
+        // Unpaired or reversed surrogates
+        assert_eq!(s(r#""\ud83d""#), None);
+        assert_eq!(s(r#""\ud83dx""#), None);
+        assert_eq!(s(r#""\ude00\ud83d""#), None);
+        assert_eq!(s(r#""\u12""#), None);
+        assert_eq!(s(r#""\x""#), None);
+    }
+
+    #[test]
+    fn malformed() {
+        for src in ["", "[", "[1,]", "[1 2]", "{\"a\"}", "{\"a\":1,}", "{1:2}", "nul",
+                    "\"abc", "-", "1 2", "[]]"].iter() {
+            assert_eq!(parse(src), None);
+        }
+    }
+
+    #[test]
+    fn depth() {
+        let nested = |n: usize| {
+            let mut s = String::new();
+            for _ in range(0, n) { s.push('['); }
+            for _ in range(0, n) { s.push(']'); }
+            s
+        };
+        assert!(parse(&nested(128)).is_some());
+        assert_eq!(parse(&nested(129)), None);
+        // Must not overflow the stack
+        assert_eq!(parse(&nested(1000000)), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let doc = Json::Object(vec!(
+            ("s".to_string(), Json::String("\"\\\n\u{1}\u{e4}".to_string())),
+            ("n".to_string(), Json::Array(vec!(Json::Number(1.5), Json::Null))),
+        ));
+        assert_eq!(parse(&doc.to_string()), Some(doc));
+    }
+}
@@ -1,5 +1,38 @@
+use std::{intrinsics};
+
+use libc::{c_void, size_t, time_t};
+
 use core::{MAX_MESSAGE_LENGTH};
 
+pub mod requests;
+pub mod json;
+pub mod outbox;
+
+#[link(name = "sodium")]
+extern {
+    fn randombytes_buf(buf: *mut c_void, size: size_t);
+}
+
+extern {
+    fn time(t: *mut time_t) -> time_t;
+}
+
+/// Fill `buf` with cryptographically secure random bytes
+pub fn random_bytes(buf: &mut [u8]) {
+    unsafe { randombytes_buf(buf.as_mut_ptr() as *mut c_void, buf.len() as size_t); }
+}
+
+/// Overwrite `buf` with zeros. Unlike a plain write this is not optimized away if
+/// `buf` is dropped afterwards
+pub fn zero_memory(buf: &mut [u8]) {
+    unsafe { intrinsics::volatile_set_memory(buf.as_mut_ptr(), 0, buf.len()); }
+}
+
+/// Returns the number of seconds since the epoch
+pub fn unix_time() -> u64 {
+    unsafe { time(0 as *mut time_t) as u64 }
+}
+
 pub fn split_message(mut m: &str) -> Vec<&str> {
     let mut ret = vec!();
     let mut last_whitespace = false;
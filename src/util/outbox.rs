@@ -0,0 +1,360 @@
+//! Queueing messages for offline friends and tracking their delivery.
+//!
+//! Messages are split with `split_message` and sent once the friend is online.
+//! Every part is tracked by its receipt number until the friend's client confirms
+//! it with a `ReadReceipt`. Parts that are not confirmed when the friend goes
+//! offline are sent again after the friend reconnects, so a friend can receive a
+//! part more than once. Messages are bound to the friend's public key and fail if
+//! the friend is deleted, even if toxcore gives the number to a new friend.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::path::{Path};
+//! use tox::core::*;
+//! use tox::util::outbox::{Outbox, OutboxEvent};
+//!
+//! let (tox, events) = ToxControl::new(ToxOptions::new()).unwrap();
+//! let path = Path::new("outbox.json");
+//! let (mut outbox, _) = Outbox::load(path, &tox).unwrap_or((Outbox::new(), vec!()));
+//! outbox.send(&tox, 0, 1, "Hello");
+//! while let Ok(ev) = events.recv_sync() {
+//!     for ev in outbox.handle(&tox, &ev) {
+//!         match ev {
+//!             OutboxEvent::Delivered(id) => println!("{} delivered", id),
+//!             OutboxEvent::Failed(id, why) => println!("{} failed: {:?}", id, why),
+//!         }
+//!     }
+//!     outbox.save(path).unwrap();
+//! }
+//! ```
+
+use std::{error, fmt, io};
+use std::fs::{File};
+use std::io::{Read};
+use std::path::{Path};
+
+use core::{ToxControl, Event, ClientId, ConnectionStatus, FriendError};
+use profile;
+use util::{split_message};
+use util::json::{self, Json};
+
+/// Why a message could not be delivered
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FailReason {
+    /// The friend doesn't exist (anymore)
+    NoFriend,
+    /// The friend went offline too often before confirming the message
+    TooManyAttempts,
+}
+
+/// What happened to a message passed to `Outbox::send`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OutboxEvent {
+    /// All parts of the message with the id have been confirmed
+    Delivered(u64),
+    Failed(u64, FailReason),
+}
+
+/// Errors of `Outbox::load`
+#[derive(Debug)]
+pub enum OutboxError {
+    /// The file could not be read
+    Io(io::Error),
+    /// The file is not a valid outbox
+    Json,
+    /// The backend thread has stopped
+    Disconnected,
+}
+
+impl error::Error for OutboxError {
+    fn description(&self) -> &str {
+        match *self {
+            OutboxError::Io(ref e)    => e.description(),
+            OutboxError::Json         => "not a valid outbox",
+            OutboxError::Disconnected => "backend thread has stopped",
+        }
+    }
+}
+
+impl fmt::Display for OutboxError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(error::Error::description(self))
+    }
+}
+
+impl From<io::Error> for OutboxError {
+    fn from(e: io::Error) -> OutboxError {
+        OutboxError::Io(e)
+    }
+}
+
+struct Part {
+    text: String,
+    /// The receipt number while the part is in flight
+    receipt: Option<u32>,
+    acked: bool,
+}
+
+struct Message {
+    id: u64,
+    fnum: i32,
+    friend: ClientId,
+    parts: Vec<Part>,
+    /// How often the friend went offline while parts were in flight
+    attempts: u32,
+}
+
+impl Message {
+    fn is_delivered(&self) -> bool {
+        self.parts.iter().all(|p| p.acked)
+    }
+}
+
+/// A queue of outgoing messages
+pub struct Outbox {
+    /// In the order in which they were sent
+    messages: Vec<Message>,
+    max_attempts: Option<u32>,
+}
+
+impl Outbox {
+    /// Create an empty outbox that retries messages forever
+    #[inline]
+    pub fn new() -> Outbox {
+        Outbox { messages: Vec::new(), max_attempts: None }
+    }
+
+    /// Give up on a message after the friend went offline `n` times before
+    /// confirming it
+    #[inline]
+    pub fn max_attempts(mut self, n: u32) -> Outbox {
+        self.max_attempts = Some(n);
+        self
+    }
+
+    /// Returns the number of messages to the friend that have not been delivered
+    pub fn pending(&self, fnum: i32) -> usize {
+        self.messages.iter().filter(|m| m.fnum == fnum).count()
+    }
+
+    /// Queue a message to the friend and send it if the friend is online. `id` is
+    /// used in the events about the message. Empty messages are delivered
+    /// immediately
+    pub fn send(&mut self, tox: &ToxControl, fnum: i32, id: u64,
+                msg: &str) -> Vec<OutboxEvent> {
+        let friend = match tox.get_client_id(fnum) {
+            Ok(friend) => friend,
+            Err(_) => return vec!(OutboxEvent::Failed(id, FailReason::NoFriend)),
+        };
+        if msg.len() == 0 {
+            return vec!(OutboxEvent::Delivered(id));
+        }
+        let parts = split_message(msg).into_iter().map(|text| Part {
+            text: text.to_string(),
+            receipt: None,
+            acked: false,
+        }).collect();
+        self.messages.push(Message {
+            id: id,
+            fnum: fnum,
+            friend: *friend,
+            parts: parts,
+            attempts: 0,
+        });
+        self.flush(tox, fnum)
+    }
+
+    /// Update the outbox with an event. Has to be called with all
+    /// `ConnectionStatusVar` and `ReadReceipt` events
+    pub fn handle(&mut self, tox: &ToxControl, event: &Event) -> Vec<OutboxEvent> {
+        match *event {
+            Event::ConnectionStatusVar(fnum, ConnectionStatus::Online) =>
+                self.flush(tox, fnum),
+            Event::ConnectionStatusVar(fnum, ConnectionStatus::Offline) =>
+                self.reset(fnum),
+            Event::ReadReceipt(fnum, receipt) => self.ack(fnum, receipt),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Send all parts to the friend that are not in flight. toxcore reuses the
+    /// numbers of deleted friends, so messages to a friend whose key doesn't match
+    /// the current one fail with `NoFriend`
+    fn flush(&mut self, tox: &ToxControl, fnum: i32) -> Vec<OutboxEvent> {
+        let current = match tox.get_client_id(fnum) {
+            Ok(friend) => Some(*friend),
+            Err(FriendError::NotFound) => None,
+            Err(_) => return Vec::new(),
+        };
+        let events: Vec<_> = self.messages.iter().filter(|m| {
+            m.fnum == fnum && Some(&m.friend) != current.as_ref()
+        }).map(|m| OutboxEvent::Failed(m.id, FailReason::NoFriend)).collect();
+        self.remove_finished(fnum, &events);
+
+        'messages: for msg in self.messages.iter_mut().filter(|m| m.fnum == fnum) {
+            for part in msg.parts.iter_mut().filter(|p| !p.acked && p.receipt.is_none()) {
+                match tox.send_message(fnum, part.text.clone()) {
+                    Ok(receipt) => part.receipt = Some(receipt),
+                    // Offline
+                    Err(_) => break 'messages,
+                }
+            }
+        }
+        events
+    }
+
+    /// Mark the parts in flight to the friend for sending again
+    fn reset(&mut self, fnum: i32) -> Vec<OutboxEvent> {
+        let max = self.max_attempts;
+        let mut events = Vec::new();
+        for msg in self.messages.iter_mut().filter(|m| m.fnum == fnum) {
+            let mut in_flight = false;
+            for part in msg.parts.iter_mut().filter(|p| !p.acked) {
+                in_flight |= part.receipt.take().is_some();
+            }
+            if in_flight {
+                msg.attempts += 1;
+                if max.map(|max| msg.attempts >= max).unwrap_or(false) {
+                    events.push(OutboxEvent::Failed(msg.id, FailReason::TooManyAttempts));
+                }
+            }
+        }
+        self.remove_finished(fnum, &events);
+        events
+    }
+
+    fn ack(&mut self, fnum: i32, receipt: u32) -> Vec<OutboxEvent> {
+        let mut events = Vec::new();
+        for msg in self.messages.iter_mut().filter(|m| m.fnum == fnum) {
+            let mut hit = false;
+            for part in msg.parts.iter_mut().filter(|p| p.receipt == Some(receipt)) {
+                part.acked = true;
+                part.receipt = None;
+                hit = true;
+            }
+            if hit && msg.is_delivered() {
+                events.push(OutboxEvent::Delivered(msg.id));
+            }
+        }
+        self.remove_finished(fnum, &events);
+        events
+    }
+
+    /// Removes the messages to the friend that are mentioned in `events`
+    fn remove_finished(&mut self, fnum: i32, events: &[OutboxEvent]) {
+        let finished = |msg: &Message| events.iter().any(|ev| match *ev {
+            OutboxEvent::Delivered(id) | OutboxEvent::Failed(id, _) => id == msg.id,
+        });
+        self.messages.retain(|m| m.fnum != fnum || !finished(m));
+    }
+
+    /// Write the undelivered messages to a file. The file is replaced atomically.
+    /// Receipts are not stored; parts that are in flight are sent again after
+    /// `load`
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let messages = self.messages.iter().map(|msg| {
+            let parts = msg.parts.iter().map(|p| {
+                Json::Object(vec!(
+                    ("text".to_string(), Json::String(p.text.clone())),
+                    ("acked".to_string(), Json::Bool(p.acked)),
+                ))
+            }).collect();
+            Json::Object(vec!(
+                // Ids don't fit into JSON numbers
+                ("id".to_string(), Json::String(msg.id.to_string())),
+                ("friend".to_string(), Json::String(format!("{}", msg.friend))),
+                ("attempts".to_string(), Json::Number(msg.attempts as f64)),
+                ("parts".to_string(), Json::Array(parts)),
+            ))
+        }).collect();
+        let doc = Json::Object(vec!(("messages".to_string(), Json::Array(messages))));
+        profile::write_atomic(path, doc.to_string().as_bytes(), 0)
+    }
+
+    /// Load messages written by `save`. Messages to friends that no longer exist
+    /// are dropped and returned as `Failed` events. Queued messages are sent when
+    /// `handle` sees the friends come online
+    pub fn load(path: &Path,
+                tox: &ToxControl) -> Result<(Outbox, Vec<OutboxEvent>), OutboxError> {
+        let mut s = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut s));
+        let doc = match json::parse(&s) {
+            Some(doc) => doc,
+            None => return Err(OutboxError::Json),
+        };
+        let list = match doc.get("messages").and_then(|m| m.as_array()) {
+            Some(list) => list,
+            None => return Err(OutboxError::Json),
+        };
+        let mut outbox = Outbox::new();
+        let mut events = Vec::new();
+        for entry in list.iter() {
+            let id = entry.get("id").and_then(|i| i.as_str())
+                          .and_then(|i| i.parse().ok());
+            let friend = entry.get("friend").and_then(|f| f.as_str())
+                              .and_then(|f| f.parse::<ClientId>().ok());
+            let attempts = entry.get("attempts").and_then(|a| a.as_f64());
+            let parts = entry.get("parts").and_then(|p| p.as_array()).map(|parts| {
+                parts.iter().map(|p| {
+                    match (p.get("text").and_then(|t| t.as_str()),
+                           p.get("acked").and_then(|a| a.as_bool())) {
+                        (Some(text), Some(acked)) => Some(Part {
+                            text: text.to_string(),
+                            receipt: None,
+                            acked: acked,
+                        }),
+                        _ => None,
+                    }
+                }).collect::<Option<Vec<Part>>>()
+            });
+            let (id, friend, attempts, parts) = match (id, friend, attempts, parts) {
+                (Some(i), Some(f), Some(a), Some(Some(p))) => (i, f, a as u32, p),
+                _ => return Err(OutboxError::Json),
+            };
+            let fnum = match tox.get_friend_number(Box::new(friend.clone())) {
+                Ok(fnum) => fnum,
+                Err(FriendError::Disconnected) => return Err(OutboxError::Disconnected),
+                Err(_) => {
+                    events.push(OutboxEvent::Failed(id, FailReason::NoFriend));
+                    continue;
+                },
+            };
+            outbox.messages.push(Message {
+                id: id,
+                fnum: fnum,
+                friend: friend,
+                parts: parts,
+                attempts: attempts,
+            });
+        }
+        Ok((outbox, events))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::{ToxControl, ToxOptions, Event, ConnectionStatus};
+    use super::{Outbox, OutboxEvent, FailReason};
+
+    #[test]
+    fn reused_friend_number() {
+        let (tox, _events) = ToxControl::new(ToxOptions::new()).unwrap();
+        let (old, _old_events) = ToxControl::new(ToxOptions::new()).unwrap();
+        let (new, _new_events) = ToxControl::new(ToxOptions::new()).unwrap();
+        let old = old.get_address().unwrap().client_id().clone();
+        let new = new.get_address().unwrap().client_id().clone();
+
+        let fnum = tox.add_friend_norequest(Box::new(old)).unwrap();
+        let mut outbox = Outbox::new();
+        assert_eq!(outbox.send(&tox, fnum, 1, "Hello"), vec!());
+        assert_eq!(outbox.pending(fnum), 1);
+
+        tox.del_friend(fnum).unwrap();
+        assert_eq!(tox.add_friend_norequest(Box::new(new)).unwrap(), fnum);
+        let online = Event::ConnectionStatusVar(fnum, ConnectionStatus::Online);
+        assert_eq!(outbox.handle(&tox, &online),
+                   vec!(OutboxEvent::Failed(1, FailReason::NoFriend)));
+        assert_eq!(outbox.pending(fnum), 0);
+    }
+}
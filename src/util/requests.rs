@@ -0,0 +1,232 @@
+//! Automatic handling of friend requests.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use tox::core::*;
+//! use tox::util::requests::{RequestPolicy, RequestFilter};
+//!
+//! let (tox, events) = ToxControl::new(ToxOptions::new()).unwrap();
+//! let policy = RequestPolicy::new().passphrase("let me in")
+//!                                  .rate_limit(5, 60)
+//!                                  .rotate_nospam_after(20, 600);
+//! let mut filter = RequestFilter::new(policy);
+//! while let Ok(ev) = events.recv_sync() {
+//!     if let FriendRequest(id, msg) = ev {
+//!         for decision in filter.handle(&tox, id, &msg) {
+//!             println!("{:?}", decision);
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::collections::{VecDeque};
+
+use core::{ToxControl, ClientId, ToxString, FriendError};
+use util::{random_bytes, unix_time};
+
+/// Why a friend request was rejected
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RejectReason {
+    /// The sender is on the deny list
+    Denied,
+    /// The request message does not contain the passphrase
+    WrongPassphrase,
+    /// Too many requests have been accepted recently
+    RateLimited,
+}
+
+/// What `RequestFilter` did with a friend request
+#[derive(Clone, Debug)]
+pub enum RequestEvent {
+    /// `(id, fnum)` where `fnum` is the friend number of the new friend
+    Accepted(Box<ClientId>, i32),
+    Rejected(Box<ClientId>, RejectReason),
+    /// The request was accepted but the friend could not be added
+    AddFailed(Box<ClientId>, FriendError),
+    /// The nospam has been changed to the contained value because too many requests
+    /// were rejected. The old address no longer works
+    NospamRotated([u8; 4]),
+}
+
+/// The rules applied by `RequestFilter`
+///
+/// Requests from ids on the deny list are always rejected and requests from ids on
+/// the allow list are always accepted. All other requests have to contain the
+/// passphrase and are subject to the rate limit.
+#[derive(Clone, Debug)]
+pub struct RequestPolicy {
+    allow: Vec<ClientId>,
+    deny: Vec<ClientId>,
+    passphrase: Option<String>,
+    /// `(max, window)`
+    rate_limit: Option<(usize, u64)>,
+    /// `(max, window)`
+    rotate_after: Option<(usize, u64)>,
+}
+
+impl RequestPolicy {
+    /// Create a policy that accepts all requests
+    #[inline]
+    pub fn new() -> RequestPolicy {
+        RequestPolicy {
+            allow: Vec::new(),
+            deny: Vec::new(),
+            passphrase: None,
+            rate_limit: None,
+            rotate_after: None,
+        }
+    }
+
+    /// Always accept requests from the id
+    #[inline]
+    pub fn allow(mut self, id: ClientId) -> RequestPolicy {
+        self.allow.push(id);
+        self
+    }
+
+    /// Always reject requests from the id
+    #[inline]
+    pub fn deny(mut self, id: ClientId) -> RequestPolicy {
+        self.deny.push(id);
+        self
+    }
+
+    /// Only accept requests whose message contains `passphrase`
+    #[inline]
+    pub fn passphrase(mut self, passphrase: &str) -> RequestPolicy {
+        self.passphrase = Some(passphrase.to_string());
+        self
+    }
+
+    /// Accept at most `max` requests every `window` seconds
+    #[inline]
+    pub fn rate_limit(mut self, max: usize, window: u64) -> RequestPolicy {
+        self.rate_limit = Some((max, window));
+        self
+    }
+
+    /// Change the nospam once `max` requests have been rejected within `window`
+    /// seconds
+    #[inline]
+    pub fn rotate_nospam_after(mut self, max: usize, window: u64) -> RequestPolicy {
+        self.rotate_after = Some((max, window));
+        self
+    }
+
+    fn matches_passphrase(&self, msg: &ToxString) -> bool {
+        let pass = match self.passphrase {
+            Some(ref pass) => pass.as_bytes(),
+            None => return true,
+        };
+        pass.len() == 0 || msg.as_bytes().windows(pass.len()).any(|w| w == pass)
+    }
+}
+
+/// Applies a `RequestPolicy` to the incoming friend requests
+pub struct RequestFilter {
+    policy: RequestPolicy,
+    /// Times at which friends were added while a rate limit was set
+    accepted: VecDeque<u64>,
+    /// Times at which requests were rejected
+    rejected: VecDeque<u64>,
+}
+
+/// Removes the times that are older than `window` seconds
+fn expire(times: &mut VecDeque<u64>, now: u64, window: u64) {
+    while times.front().map(|&t| t + window <= now).unwrap_or(false) {
+        times.pop_front();
+    }
+}
+
+impl RequestFilter {
+    #[inline]
+    pub fn new(policy: RequestPolicy) -> RequestFilter {
+        RequestFilter {
+            policy: policy,
+            accepted: VecDeque::new(),
+            rejected: VecDeque::new(),
+        }
+    }
+
+    /// Returns the policy
+    #[inline]
+    pub fn policy(&self) -> &RequestPolicy {
+        &self.policy
+    }
+
+    /// Replace the policy. The rate limit history is kept
+    #[inline]
+    pub fn set_policy(&mut self, policy: RequestPolicy) {
+        self.policy = policy;
+    }
+
+    /// Decide on a `FriendRequest` event and add the friend if the request is
+    /// accepted. Returns what has been done
+    pub fn handle(&mut self, tox: &ToxControl, id: Box<ClientId>,
+                  msg: &ToxString) -> Vec<RequestEvent> {
+        let now = unix_time();
+        let mut events = Vec::new();
+        match self.decide(&*id, msg, now) {
+            Ok(()) => match tox.add_friend_norequest(id.clone()) {
+                Ok(fnum) => {
+                    // Only needed for the rate limit. Failed additions don't count
+                    if self.policy.rate_limit.is_some() {
+                        self.accepted.push_back(now);
+                    }
+                    events.push(RequestEvent::Accepted(id, fnum));
+                },
+                Err(e) => events.push(RequestEvent::AddFailed(id, e)),
+            },
+            Err(reason) => {
+                events.push(RequestEvent::Rejected(id, reason));
+                if let Some(nospam) = self.record_rejection(tox, now) {
+                    events.push(RequestEvent::NospamRotated(nospam));
+                }
+            },
+        }
+        events
+    }
+
+    fn decide(&mut self, id: &ClientId, msg: &ToxString,
+              now: u64) -> Result<(), RejectReason> {
+        if self.policy.deny.contains(id) {
+            return Err(RejectReason::Denied);
+        }
+        if self.policy.allow.contains(id) {
+            return Ok(());
+        }
+        if !self.policy.matches_passphrase(msg) {
+            return Err(RejectReason::WrongPassphrase);
+        }
+        if let Some((max, window)) = self.policy.rate_limit {
+            expire(&mut self.accepted, now, window);
+            if self.accepted.len() >= max {
+                return Err(RejectReason::RateLimited);
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a rejection and rotates the nospam if there have been too many
+    fn record_rejection(&mut self, tox: &ToxControl, now: u64) -> Option<[u8; 4]> {
+        let (max, window) = match self.policy.rotate_after {
+            Some(r) => r,
+            None => return None,
+        };
+        self.rejected.push_back(now);
+        expire(&mut self.rejected, now, window);
+        if self.rejected.len() < max {
+            return None;
+        }
+        let mut nospam = [0u8; 4];
+        random_bytes(&mut nospam);
+        match tox.set_nospam(nospam) {
+            Ok(()) => {
+                self.rejected.clear();
+                Some(nospam)
+            },
+            Err(_) => None,
+        }
+    }
+}